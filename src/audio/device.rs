@@ -0,0 +1,92 @@
+use crate::audio::AudioSink;
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{FromSample, SampleFormat, SizedSample, StreamConfig};
+use std::collections::VecDeque;
+use std::io;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+// Seconds of samples that can wait for the device before more are dropped,
+// which only happens when the interpreter runs faster than real time
+const MAX_QUEUED: f64 = 0.2;
+
+// Plays through the default output device with cpal, on any platform it
+// supports. The device picks the sample rate, so samples have to be
+// generated at `sample_rate()`.
+pub struct DeviceSink {
+    queue: Arc<Mutex<VecDeque<i16>>>,
+    sample_rate: u32,
+}
+
+impl DeviceSink {
+    pub fn open() -> io::Result<Self> {
+        let queue = Arc::new(Mutex::new(VecDeque::new()));
+        let (opened, result) = mpsc::channel();
+        let stream_queue = queue.clone();
+        // Streams can't move between threads everywhere, so one thread keeps it open
+        thread::spawn(move || {
+            let _stream = match start_stream(stream_queue) {
+                Ok((stream, sample_rate)) => {
+                    let _ = opened.send(Ok(sample_rate));
+                    stream
+                },
+                Err(e) => {
+                    let _ = opened.send(Err(e));
+                    return;
+                },
+            };
+            loop {
+                thread::park();
+            }
+        });
+        let sample_rate = result.recv().map_err(|_| io::Error::other("audio thread stopped"))??;
+        return Ok(DeviceSink { queue, sample_rate });
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        return self.sample_rate;
+    }
+}
+
+impl AudioSink for DeviceSink {
+    fn write(&mut self, samples: &[i16]) {
+        let mut queue = self.queue.lock().unwrap();
+        if queue.len() + samples.len() <= (self.sample_rate as f64 * MAX_QUEUED) as usize {
+            queue.extend(samples);
+        }
+    }
+}
+
+fn start_stream(queue: Arc<Mutex<VecDeque<i16>>>) -> io::Result<(cpal::Stream, u32)> {
+    let device = cpal::default_host().default_output_device()
+        .ok_or_else(|| io::Error::other("no output device"))?;
+    let supported = device.default_output_config().map_err(io::Error::other)?;
+    let config = supported.config();
+    let stream = match supported.sample_format() {
+        SampleFormat::F32 => build_stream::<f32>(&device, &config, queue)?,
+        SampleFormat::I16 => build_stream::<i16>(&device, &config, queue)?,
+        SampleFormat::U16 => build_stream::<u16>(&device, &config, queue)?,
+        format => return Err(io::Error::other(format!("unsupported sample format {:?}", format))),
+    };
+    stream.play().map_err(io::Error::other)?;
+    return Ok((stream, config.sample_rate.0));
+}
+
+// Plays the same sample on every channel, and silence once the queue runs dry
+fn build_stream<T>(device: &cpal::Device, config: &StreamConfig, queue: Arc<Mutex<VecDeque<i16>>>) -> io::Result<cpal::Stream>
+where
+    T: SizedSample + FromSample<i16>,
+{
+    let channels = config.channels as usize;
+    let stream = device.build_output_stream(config, move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+        let mut queue = queue.lock().unwrap();
+        for frame in data.chunks_mut(channels) {
+            let sample = T::from_sample(queue.pop_front().unwrap_or(0));
+            for out in frame.iter_mut() {
+                *out = sample;
+            }
+        }
+    }, |e| eprintln!("Audio error: {}", e), None).map_err(io::Error::other)?;
+    return Ok(stream);
+}
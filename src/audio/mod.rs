@@ -0,0 +1,177 @@
+#[cfg(any(feature = "cpal", not(target_os = "linux")))]
+mod device;
+#[cfg(all(not(feature = "cpal"), target_os = "linux"))]
+mod pipe;
+mod wav;
+
+#[cfg(any(feature = "cpal", not(target_os = "linux")))]
+pub use device::DeviceSink;
+#[cfg(all(not(feature = "cpal"), target_os = "linux"))]
+pub use pipe::PipeSink;
+pub use wav::WavSink;
+
+use std::io;
+
+// The sound timer and the beep are both clocked at 60Hz
+const TICK_RATE: u32 = 60;
+
+// Receives generated samples, e.g. a sound card or a file
+pub trait AudioSink: Send {
+    fn write(&mut self, samples: &[i16]);
+}
+
+// Discards everything, for running without sound
+pub struct NullSink;
+
+impl AudioSink for NullSink {
+    fn write(&mut self, _samples: &[i16]) {}
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Waveform {
+    Square,
+    Triangle,
+    Sawtooth,
+    Sine,
+}
+
+impl Waveform {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "square" => Some(Waveform::Square),
+            "triangle" => Some(Waveform::Triangle),
+            "sawtooth" | "saw" => Some(Waveform::Sawtooth),
+            "sine" => Some(Waveform::Sine),
+            _ => None,
+        }
+    }
+
+    // Amplitude in [-1, 1] at the given phase in [0, 1)
+    fn sample(&self, phase: f64) -> f64 {
+        match self {
+            Waveform::Square => if phase < 0.5 { 1.0 } else { -1.0 },
+            Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+            Waveform::Sawtooth => 2.0 * phase - 1.0,
+            Waveform::Sine => (phase * 2.0 * std::f64::consts::PI).sin(),
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct AudioSettings {
+    pub sample_rate: u32,
+    pub frequency: f64,
+    // Between 0.0 and 1.0
+    pub volume: f64,
+    pub waveform: Waveform,
+}
+
+impl AudioSettings {
+    pub fn new() -> Self {
+        AudioSettings {
+            sample_rate: 44100,
+            frequency: 440.0,
+            volume: 0.25,
+            waveform: Waveform::Square,
+        }
+    }
+}
+
+// XO-CHIP replaces the beep with a 128 bit pattern played back at a set pitch
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Tone {
+    pub pattern: Option<[u8; 16]>,
+    pub pitch: u8,
+}
+
+impl Tone {
+    pub fn new() -> Self {
+        Tone {
+            pattern: None,
+            pitch: 64,
+        }
+    }
+
+    // Pattern bits played per second, where pitch 64 is 4000Hz
+    fn playback_rate(&self) -> f64 {
+        return 4000.0 * 2f64.powf((self.pitch as f64 - 64.0) / 48.0);
+    }
+}
+
+// Turns the sound timer into samples, one 60Hz tick at a time
+pub struct Beeper {
+    settings: AudioSettings,
+    phase: f64,
+}
+
+impl Beeper {
+    pub fn new(settings: AudioSettings) -> Self {
+        Beeper {
+            settings,
+            phase: 0.0,
+        }
+    }
+
+    // Generates one tick of audio, silent unless the sound timer is running
+    pub fn tick(&mut self, sound_timer: u8, tone: &Tone) -> Vec<i16> {
+        let len = (self.settings.sample_rate / TICK_RATE) as usize;
+        if sound_timer == 0 {
+            self.phase = 0.0;
+            return vec![0; len];
+        }
+        let amplitude = self.settings.volume.clamp(0.0, 1.0) * i16::MAX as f64;
+        let (step, pattern) = match tone.pattern {
+            Some(pattern) => (tone.playback_rate() / 128.0, Some(pattern)),
+            None => (self.settings.frequency, None),
+        };
+        let step = step / self.settings.sample_rate as f64;
+        let mut samples = Vec::with_capacity(len);
+        for _ in 0..len {
+            let level = match pattern {
+                Some(pattern) => {
+                    let bit = (self.phase * 128.0) as usize % 128;
+                    if pattern[bit / 8] & (0x80 >> (bit % 8)) != 0 { 1.0 } else { -1.0 }
+                },
+                None => self.settings.waveform.sample(self.phase),
+            };
+            samples.push((level * amplitude) as i16);
+            self.phase = (self.phase + step).fract();
+        }
+        return samples;
+    }
+}
+
+// Sound for the interpreter, which hands it the sound timer once a frame so
+// every frame run produces exactly one frame of samples
+pub struct Audio {
+    beeper: Beeper,
+    sink: Box<dyn AudioSink>,
+}
+
+impl Audio {
+    pub fn new(settings: AudioSettings, sink: Box<dyn AudioSink>) -> Self {
+        Audio {
+            beeper: Beeper::new(settings),
+            sink,
+        }
+    }
+
+    pub fn frame(&mut self, sound_timer: u8, tone: &Tone) {
+        let samples = self.beeper.tick(sound_timer, tone);
+        self.sink.write(&samples);
+    }
+}
+
+// The sound card, through cpal when built with it and aplay otherwise. The
+// sample rate is changed to whatever the device wants.
+#[cfg(any(feature = "cpal", not(target_os = "linux")))]
+pub fn open_output(settings: &mut AudioSettings) -> io::Result<Box<dyn AudioSink>> {
+    let sink = DeviceSink::open()?;
+    settings.sample_rate = sink.sample_rate();
+    return Ok(Box::new(sink));
+}
+
+#[cfg(all(not(feature = "cpal"), target_os = "linux"))]
+pub fn open_output(settings: &mut AudioSettings) -> io::Result<Box<dyn AudioSink>> {
+    return Ok(Box::new(PipeSink::spawn(settings.sample_rate)?));
+}
@@ -0,0 +1,65 @@
+use crate::audio::AudioSink;
+use std::io;
+use std::io::prelude::*;
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, SyncSender};
+use std::thread::{self, JoinHandle};
+
+// Frames of samples that can wait for the player before more are dropped,
+// which only happens when the interpreter runs faster than real time
+const QUEUE_FRAMES: usize = 12;
+
+// Streams raw samples into aplay, so sound works on Linux without linking
+// against a platform audio library. Writing never blocks the interpreter.
+pub struct PipeSink {
+    samples: Option<SyncSender<Vec<i16>>>,
+    writer: Option<JoinHandle<()>>,
+}
+
+impl PipeSink {
+    pub fn spawn(sample_rate: u32) -> io::Result<Self> {
+        let mut player = Command::new("aplay")
+            .args(["-q", "-t", "raw", "-f", "S16_LE", "-c", "1", "-r"])
+            .arg(sample_rate.to_string())
+            .stdin(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let mut stdin = player.stdin.take().unwrap();
+        let (sender, receiver) = mpsc::sync_channel::<Vec<i16>>(QUEUE_FRAMES);
+        let writer = thread::spawn(move || {
+            for samples in receiver {
+                let mut bytes = Vec::with_capacity(samples.len() * 2);
+                for sample in samples {
+                    bytes.extend_from_slice(&sample.to_le_bytes());
+                }
+                // The player going away just means no more sound
+                if stdin.write_all(&bytes).is_err() {
+                    break;
+                }
+            }
+            drop(stdin);
+            let _ = player.wait();
+        });
+        return Ok(PipeSink {
+            samples: Some(sender),
+            writer: Some(writer),
+        });
+    }
+}
+
+impl AudioSink for PipeSink {
+    fn write(&mut self, samples: &[i16]) {
+        if let Some(ref sender) = self.samples {
+            let _ = sender.try_send(samples.to_vec());
+        }
+    }
+}
+
+impl Drop for PipeSink {
+    fn drop(&mut self) {
+        self.samples = None;
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+    }
+}
@@ -0,0 +1,62 @@
+use crate::audio::AudioSink;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::SeekFrom;
+
+const HEADER_LEN: u32 = 44;
+
+// Records 16-bit mono PCM to a WAV file. The header sizes are rewritten after
+// every write so the file stays valid however the process ends.
+pub struct WavSink {
+    file: File,
+    data_len: u32,
+}
+
+impl WavSink {
+    pub fn create(path: &str, sample_rate: u32) -> io::Result<Self> {
+        let mut file = File::create(path)?;
+        let byte_rate = sample_rate * 2;
+        let mut header = Vec::with_capacity(HEADER_LEN as usize);
+        header.extend_from_slice(b"RIFF");
+        header.extend_from_slice(&(HEADER_LEN - 8).to_le_bytes());
+        header.extend_from_slice(b"WAVEfmt ");
+        header.extend_from_slice(&16u32.to_le_bytes());
+        header.extend_from_slice(&1u16.to_le_bytes()); // PCM
+        header.extend_from_slice(&1u16.to_le_bytes()); // Mono
+        header.extend_from_slice(&sample_rate.to_le_bytes());
+        header.extend_from_slice(&byte_rate.to_le_bytes());
+        header.extend_from_slice(&2u16.to_le_bytes()); // Block align
+        header.extend_from_slice(&16u16.to_le_bytes()); // Bits per sample
+        header.extend_from_slice(b"data");
+        header.extend_from_slice(&0u32.to_le_bytes());
+        file.write_all(&header)?;
+        return Ok(WavSink {
+            file,
+            data_len: 0,
+        });
+    }
+
+    fn append(&mut self, samples: &[i16]) -> io::Result<()> {
+        let mut bytes = Vec::with_capacity(samples.len() * 2);
+        for sample in samples {
+            bytes.extend_from_slice(&sample.to_le_bytes());
+        }
+        self.file.seek(SeekFrom::End(0))?;
+        self.file.write_all(&bytes)?;
+        self.data_len += bytes.len() as u32;
+        self.file.seek(SeekFrom::Start(4))?;
+        self.file.write_all(&(HEADER_LEN - 8 + self.data_len).to_le_bytes())?;
+        self.file.seek(SeekFrom::Start(40))?;
+        self.file.write_all(&self.data_len.to_le_bytes())?;
+        return Ok(());
+    }
+}
+
+impl AudioSink for WavSink {
+    fn write(&mut self, samples: &[i16]) {
+        if let Err(e) = self.append(samples) {
+            eprintln!("Failed to write audio: {}", e);
+        }
+    }
+}
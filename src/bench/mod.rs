@@ -0,0 +1,122 @@
+use crate::display::{frame_channel, Display, FrameReader};
+use crate::interpreter::Interpreter;
+use crate::platform::Platform;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::{thread, time};
+
+// Instructions run between checks of the clock
+const BATCH: u32 = 1000;
+
+// Pixels drawn between publishes in the handoff benchmark, one 8x15 sprite
+const SPRITE_PIXELS: usize = 8 * 15;
+
+// Runs the interpreter unthrottled while another thread pulls frames as fast
+// as it can, standing in for the renderer, and reports the throughput of both
+pub fn run(interpreter: &mut Interpreter, mut frames: FrameReader, seconds: f64) {
+    let done = Arc::new(AtomicBool::new(false));
+    let frames_read = Arc::new(AtomicU64::new(0));
+    let reader = {
+        let done = done.clone();
+        let frames_read = frames_read.clone();
+        thread::spawn(move || {
+            let mut frame = Vec::new();
+            while !done.load(Ordering::SeqCst) {
+                frame.clear();
+                frame.extend_from_slice(&frames.read().pixels);
+                frames_read.fetch_add(1, Ordering::SeqCst);
+            }
+        })
+    };
+
+    interpreter.set_throttled(false);
+    let duration = time::Duration::from_secs_f64(seconds);
+    let start = time::Instant::now();
+    let mut instructions: u64 = 0;
+    while interpreter.is_running() && start.elapsed() < duration {
+        instructions += interpreter.run(BATCH) as u64;
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+    done.store(true, Ordering::SeqCst);
+    reader.join().unwrap();
+
+    println!("{} instructions in {:.2}s: {:.0} instructions/s", instructions, elapsed, instructions as f64 / elapsed);
+    let frames_read = frames_read.load(Ordering::SeqCst);
+    println!("{} frames read: {:.0} frames/s", frames_read, frames_read as f64 / elapsed);
+}
+
+// Draws sprites pixel by pixel on one thread, publishing a copy of the screen
+// after each, while another copies out whole frames. Both sides do the same
+// work through a mutex and then through the triple buffer, so the two
+// handoffs can be compared on the same machine.
+pub fn handoff(seconds: f64) {
+    let duration = time::Duration::from_secs_f64(seconds / 2.0);
+    let blank = Display::new(Platform::Chip8).frame();
+    let pixels = blank.pixels.len();
+
+    let shared = Arc::new(Mutex::new(blank.clone()));
+    let reader = shared.clone();
+    let mut display = blank.clone();
+    let (drawn, read) = race(duration, move |pixel| {
+        display.pixels[pixel % pixels] ^= 1;
+        if pixel % SPRITE_PIXELS == SPRITE_PIXELS - 1 {
+            shared.lock().unwrap().clone_from(&display);
+        }
+    }, move |frame| {
+        frame.clear();
+        frame.extend_from_slice(&reader.lock().unwrap().pixels);
+    });
+    report("mutex", drawn, read, duration);
+
+    let (mut writer, mut reader) = frame_channel(Platform::Chip8);
+    let mut display = blank;
+    let (drawn, read) = race(duration, move |pixel| {
+        display.pixels[pixel % pixels] ^= 1;
+        if pixel % SPRITE_PIXELS == SPRITE_PIXELS - 1 {
+            writer.buffer().clone_from(&display);
+            writer.publish();
+        }
+    }, move |frame| {
+        frame.clear();
+        frame.extend_from_slice(&reader.read().pixels);
+    });
+    report("triple buffer", drawn, read, duration);
+}
+
+// Calls `draw` with successive pixel numbers on this thread and `read` on
+// another until `duration` is up, returning how many times each ran
+fn race<D, R>(duration: time::Duration, mut draw: D, mut read: R) -> (u64, u64)
+where
+    D: FnMut(usize),
+    R: FnMut(&mut Vec<u8>) + Send + 'static,
+{
+    let done = Arc::new(AtomicBool::new(false));
+    let reader = {
+        let done = done.clone();
+        thread::spawn(move || {
+            let mut frame = Vec::new();
+            let mut frames: u64 = 0;
+            while !done.load(Ordering::SeqCst) {
+                read(&mut frame);
+                frames += 1;
+            }
+            frames
+        })
+    };
+
+    let start = time::Instant::now();
+    let mut pixels = 0;
+    while start.elapsed() < duration {
+        for _ in 0..BATCH {
+            draw(pixels);
+            pixels += 1;
+        }
+    }
+    done.store(true, Ordering::SeqCst);
+    return (pixels as u64, reader.join().unwrap());
+}
+
+fn report(name: &str, drawn: u64, read: u64, duration: time::Duration) {
+    let seconds = duration.as_secs_f64();
+    println!("{:<16} {:>12.0} pixels drawn/s  {:>10.0} frames read/s", name, drawn as f64 / seconds, read as f64 / seconds);
+}
@@ -0,0 +1,205 @@
+use crate::config::Config;
+use crate::platform::Platform;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Where cheat files live unless the config sets cheats.dir
+const DEFAULT_DIR: &str = "cheats";
+
+// Keeps the byte at `addr` set to `value`
+#[derive(Clone, PartialEq, Debug)]
+pub struct Cheat {
+    pub addr: u16,
+    pub value: u8,
+    pub description: String,
+}
+
+pub fn dir(config: &Config) -> PathBuf {
+    let dir = config.get("cheats.dir").and_then(|v| v.as_str()).unwrap_or(DEFAULT_DIR);
+    return PathBuf::from(dir);
+}
+
+// Cheats are kept per ROM in <dir>/<sha1>.cht, one per line as a hex address
+// and value followed by an optional description:
+//
+//     # BRIX
+//     02F8 05 infinite lives
+pub fn path_for(dir: &Path, rom_hash: &str) -> PathBuf {
+    return dir.join(format!("{}.cht", rom_hash));
+}
+
+pub fn parse(text: &str) -> Result<Vec<Cheat>, String> {
+    let mut cheats = Vec::new();
+    for (line_idx, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut fields = line.splitn(3, char::is_whitespace);
+        let addr = fields.next().and_then(|addr| u16::from_str_radix(addr, 16).ok());
+        let value = fields.next().and_then(|value| u8::from_str_radix(value, 16).ok());
+        match (addr, value) {
+            (Some(addr), Some(value)) => cheats.push(Cheat {
+                addr,
+                value,
+                description: fields.next().unwrap_or("").trim().to_string(),
+            }),
+            _ => return Err(format!("line {}: expected a hex address and value", line_idx + 1)),
+        }
+    }
+    return Ok(cheats);
+}
+
+// A missing file just means there are no cheats for the ROM yet
+pub fn load(path: &Path) -> io::Result<Vec<Cheat>> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    return parse(&text).map_err(|e| {
+        io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), e))
+    });
+}
+
+pub fn save(path: &Path, cheats: &[Cheat]) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut text = String::new();
+    for cheat in cheats {
+        text.push_str(&format!("{:04X} {:02X}", cheat.addr, cheat.value));
+        if !cheat.description.is_empty() {
+            text.push(' ');
+            text.push_str(&cheat.description);
+        }
+        text.push('\n');
+    }
+    return fs::write(path, text);
+}
+
+// How a byte has to have changed to stay in a search
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Comparison {
+    Equal,
+    Increased,
+    Decreased,
+}
+
+// Narrows down which addresses hold a value by comparing memory between
+// frames, e.g. losing a life and keeping only the bytes that decreased
+pub struct CheatSearch {
+    // Remaining addresses and their values when last compared
+    candidates: Vec<(u16, u8)>,
+}
+
+impl CheatSearch {
+    // Every address the platform has starts out as a candidate
+    pub fn new(ram: &[u8], platform: Platform) -> Self {
+        let size = platform.memory_size().min(ram.len());
+        CheatSearch {
+            candidates: ram[..size].iter().enumerate().map(|(addr, value)| (addr as u16, *value)).collect(),
+        }
+    }
+
+    pub fn filter(&mut self, ram: &[u8], comparison: Comparison) {
+        self.candidates.retain(|(addr, last)| {
+            let value = ram[*addr as usize];
+            match comparison {
+                Comparison::Equal => value == *last,
+                Comparison::Increased => value > *last,
+                Comparison::Decreased => value < *last,
+            }
+        });
+        for (addr, last) in self.candidates.iter_mut() {
+            *last = ram[*addr as usize];
+        }
+    }
+
+    pub fn candidates(&self) -> &[(u16, u8)] {
+        return &self.candidates;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process;
+
+    #[test]
+    fn parses_cheat_files() {
+        let cheats = parse("# BRIX\n\n02F8 05 infinite lives\n  0A1 ff\t  wide  gaps \n").unwrap();
+        assert_eq!(cheats, vec![
+            Cheat { addr: 0x02F8, value: 0x05, description: "infinite lives".to_string() },
+            Cheat { addr: 0x00A1, value: 0xFF, description: "wide  gaps".to_string() },
+        ]);
+    }
+
+    #[test]
+    fn rejects_malformed_lines_with_their_number() {
+        assert_eq!(parse("02F8 05\n02F8\n").unwrap_err(), "line 2: expected a hex address and value");
+        assert!(parse("02G8 05\n").is_err());
+        assert!(parse("10000 05\n").is_err());
+        assert!(parse("02F8 100\n").is_err());
+        assert!(parse("02F8 -1\n").is_err());
+        assert!(parse("# 02F8\n").unwrap().is_empty());
+    }
+
+    #[test]
+    fn saved_cheats_load_back() {
+        let dir = std::env::temp_dir().join(format!("chip8rs-cheats-{}", process::id()));
+        let path = path_for(&dir, "f13766c1");
+        assert_eq!(load(&path).unwrap(), Vec::new());
+        let cheats = vec![
+            Cheat { addr: 0x02F8, value: 0x05, description: "infinite lives".to_string() },
+            Cheat { addr: 0xFFFF, value: 0x00, description: String::new() },
+        ];
+        save(&path, &cheats).unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        let loaded = load(&path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(text, "02F8 05 infinite lives\nFFFF 00\n");
+        assert_eq!(loaded, cheats);
+    }
+
+    #[test]
+    fn searches_narrow_down_by_how_bytes_changed() {
+        let mut ram = vec![0x00; 0x10000];
+        ram[0x300] = 3;
+        ram[0x301] = 7;
+        ram[0x302] = 9;
+        let mut search = CheatSearch::new(&ram, Platform::Chip8);
+        assert_eq!(search.candidates().len(), 0x1000);
+
+        ram[0x300] = 2;
+        ram[0x301] = 8;
+        search.filter(&ram, Comparison::Decreased);
+        assert_eq!(search.candidates(), &[(0x300, 2)]);
+
+        let mut search = CheatSearch::new(&ram, Platform::Chip8);
+        ram[0x300] = 1;
+        ram[0x301] = 9;
+        search.filter(&ram, Comparison::Increased);
+        assert_eq!(search.candidates(), &[(0x301, 9)]);
+        // Each pass compares against the values from the one before
+        ram[0x301] = 9;
+        search.filter(&ram, Comparison::Increased);
+        assert!(search.candidates().is_empty());
+
+        let mut search = CheatSearch::new(&ram, Platform::Chip8);
+        ram[0x300] = 0;
+        search.filter(&ram, Comparison::Equal);
+        assert_eq!(search.candidates().len(), 0x1000 - 1);
+        assert!(!search.candidates().iter().any(|(addr, _)| *addr == 0x300));
+    }
+
+    #[test]
+    fn searches_cover_the_platforms_memory() {
+        let ram = vec![0x00; 0x10000];
+        assert_eq!(CheatSearch::new(&ram, Platform::SuperChip).candidates().len(), 0x1000);
+        let search = CheatSearch::new(&ram, Platform::XoChip);
+        assert_eq!(search.candidates().len(), 0x10000);
+        assert_eq!(search.candidates().last(), Some(&(0xFFFF, 0x00)));
+    }
+}
@@ -0,0 +1,175 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+
+// Where settings are read from when no --config is given
+pub const DEFAULT_PATH: &str = "chip8rs.toml";
+
+// A value from one of our TOML config files, where dates are read as strings
+#[derive(Clone, PartialEq, Debug)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    Array(Vec<Value>),
+    Table(BTreeMap<String, Value>),
+}
+
+impl Value {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_integer(&self) -> Option<i64> {
+        match self {
+            Value::Integer(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Boolean(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&Vec<Value>> {
+        match self {
+            Value::Array(a) => Some(a),
+            _ => None,
+        }
+    }
+
+    pub fn as_table(&self) -> Option<&BTreeMap<String, Value>> {
+        match self {
+            Value::Table(t) => Some(t),
+            _ => None,
+        }
+    }
+
+    // Looks up a dotted path such as "palette.default"
+    pub fn get(&self, path: &str) -> Option<&Value> {
+        let mut value = self;
+        for key in path.split('.') {
+            value = value.as_table()?.get(key)?;
+        }
+        return Some(value);
+    }
+}
+
+pub struct Config {
+    root: Value,
+}
+
+impl Config {
+    pub fn empty() -> Self {
+        Config {
+            root: Value::Table(BTreeMap::new()),
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        return Ok(Config {
+            root: parse(text)?,
+        });
+    }
+
+    // Reads the file at `path`, which is allowed to not exist
+    pub fn load(path: &str) -> io::Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(text) => Config::parse(&text).map_err(|e| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path, e))
+            }),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(Config::empty()),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn get(&self, path: &str) -> Option<&Value> {
+        return self.root.get(path);
+    }
+
+    pub fn root(&self) -> &Value {
+        return &self.root;
+    }
+}
+
+fn parse(text: &str) -> Result<Value, String> {
+    let table: toml::Table = text.parse().map_err(|e: toml::de::Error| e.message().to_string())?;
+    return Ok(Value::from(toml::Value::Table(table)));
+}
+
+impl From<toml::Value> for Value {
+    fn from(value: toml::Value) -> Self {
+        match value {
+            toml::Value::String(s) => Value::String(s),
+            toml::Value::Integer(i) => Value::Integer(i),
+            toml::Value::Float(f) => Value::Float(f),
+            toml::Value::Boolean(b) => Value::Boolean(b),
+            toml::Value::Datetime(d) => Value::String(d.to_string()),
+            toml::Value::Array(a) => Value::Array(a.into_iter().map(Value::from).collect()),
+            toml::Value::Table(t) => Value::Table(t.into_iter().map(|(k, v)| (k, Value::from(v))).collect()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process;
+
+    #[test]
+    fn looks_up_dotted_paths() {
+        let config = Config::parse(r#"
+            speed = 700
+            [roms.pong]
+            quirks = { wrap = true }
+            [roms."pong.ch8"]
+            speed = 1000
+        "#).unwrap();
+        assert_eq!(config.get("speed"), Some(&Value::Integer(700)));
+        assert_eq!(config.get("roms.pong.quirks.wrap").and_then(|v| v.as_bool()), Some(true));
+        assert!(config.get("roms.pong").and_then(|v| v.as_table()).is_some());
+        // Missing keys and paths through things that aren't tables
+        assert_eq!(config.get("roms.tetris"), None);
+        assert_eq!(config.get("speed.max"), None);
+        assert_eq!(config.get("roms.pong.quirks.wrap.more"), None);
+        assert_eq!(config.get(""), None);
+        // Keys with dots in them can't be reached by a path
+        assert_eq!(config.get("roms.pong.ch8.speed"), None);
+        assert_eq!(Config::empty().get("speed"), None);
+    }
+
+    #[test]
+    fn missing_files_are_empty_and_broken_ones_name_the_file() {
+        let dir = std::env::temp_dir();
+        let missing = dir.join(format!("chip8rs-missing-{}.toml", process::id()));
+        assert_eq!(Config::load(missing.to_str().unwrap()).unwrap().root(), Config::empty().root());
+
+        let broken = dir.join(format!("chip8rs-broken-{}.toml", process::id()));
+        fs::write(&broken, "speed = [1, 2\n").unwrap();
+        let error = Config::load(broken.to_str().unwrap()).err().unwrap();
+        fs::remove_file(&broken).unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().starts_with(broken.to_str().unwrap()), "{}", error);
+    }
+
+    #[test]
+    fn dates_are_read_as_strings() {
+        let config = Config::parse("saved = 2020-06-01\n").unwrap();
+        assert_eq!(config.get("saved").and_then(|v| v.as_str()), Some("2020-06-01"));
+    }
+
+    #[test]
+    fn shipped_files_parse() {
+        for path in &["chip8rs.toml", "data/romdb.toml"] {
+            let text = fs::read_to_string(path).unwrap();
+            assert!(Config::parse(&text).is_ok(), "{} does not parse", path);
+        }
+    }
+}
@@ -0,0 +1,146 @@
+mod triple_buffer;
+
+pub use triple_buffer::{TripleBuffer, Reader, Writer};
+
+use crate::platform::Platform;
+
+pub type FrameReader = Reader<Frame>;
+pub type FrameWriter = Writer<Frame>;
+
+// A complete picture handed from the interpreter to a frontend
+#[derive(Clone, PartialEq, Debug)]
+pub struct Frame {
+    pub width: usize,
+    pub height: usize,
+    // Palette indices for every pixel, row by row
+    pub pixels: Vec<u8>,
+    // How many 60Hz frames the interpreter had finished when this was published
+    pub number: u64,
+}
+
+// Creates the handoff between the interpreter and a frontend, starting with a blank screen
+pub fn frame_channel(platform: Platform) -> (FrameWriter, FrameReader) {
+    return TripleBuffer::new(Display::new(platform).frame());
+}
+
+// The interpreter's own framebuffer, which is only shared by publishing frames
+pub struct Display {
+    // Row-major framebuffer sized for the current resolution. Each pixel
+    // holds one bit per XO-CHIP bitplane, so its value is a palette index.
+    pixels: Vec<u8>,
+    width: usize,
+    height: usize,
+    // Bitmask of the planes affected by drawing, clearing and scrolling
+    planes: u8,
+    platform: Platform,
+}
+
+impl Display {
+    pub fn new(platform: Platform) -> Self {
+        let (width, height) = platform.lores_size();
+        Display {
+            pixels: vec![0; width * height],
+            width,
+            height,
+            planes: 0x01,
+            platform,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    // Switches between the platform's standard and hires resolution, clearing the screen
+    pub fn set_hires(&mut self, hires: bool) {
+        let (width, height) = if hires {
+            self.platform.hires_size()
+        } else {
+            self.platform.lores_size()
+        };
+        self.width = width;
+        self.height = height;
+        self.pixels = vec![0; self.width * self.height];
+    }
+
+    pub fn get_planes(&self) -> u8 {
+        self.planes
+    }
+
+    pub fn set_planes(&mut self, planes: u8) {
+        self.planes = planes & 0x03;
+    }
+
+    // Flips the pixel on the given plane, returning true if it was turned off
+    pub fn xor_pixel(&mut self, x: usize, y: usize, plane: u8) -> bool {
+        let idx = (y % self.height) * self.width + (x % self.width);
+        let erased = self.pixels[idx] & plane != 0;
+        self.pixels[idx] ^= plane;
+        return erased;
+    }
+
+    pub fn frame(&self) -> Frame {
+        let mut frame = Frame {
+            width: 0,
+            height: 0,
+            pixels: Vec::new(),
+            number: 0,
+        };
+        self.copy_to(&mut frame);
+        return frame;
+    }
+
+    // Fills an existing frame, reusing its allocation
+    pub fn copy_to(&self, frame: &mut Frame) {
+        frame.width = self.width;
+        frame.height = self.height;
+        frame.pixels.clear();
+        frame.pixels.extend_from_slice(&self.pixels);
+    }
+
+    pub fn clear(&mut self) {
+        let mask = !self.planes;
+        for pixel in self.pixels.iter_mut() {
+            *pixel &= mask;
+        }
+    }
+
+    pub fn scroll_up(&mut self, rows: usize) {
+        self.shift_planes(0, -(rows as isize));
+    }
+
+    pub fn scroll_down(&mut self, rows: usize) {
+        self.shift_planes(0, rows as isize);
+    }
+
+    pub fn scroll_right(&mut self, cols: usize) {
+        self.shift_planes(cols as isize, 0);
+    }
+
+    pub fn scroll_left(&mut self, cols: usize) {
+        self.shift_planes(-(cols as isize), 0);
+    }
+
+    // Moves the selected planes by (dx, dy), filling uncovered pixels with zeros
+    fn shift_planes(&mut self, dx: isize, dy: isize) {
+        let mask = self.planes;
+        let old = self.pixels.clone();
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let src_x = x as isize - dx;
+                let src_y = y as isize - dy;
+                let moved = if src_x >= 0 && src_y >= 0 && (src_x as usize) < self.width && (src_y as usize) < self.height {
+                    old[src_y as usize * self.width + src_x as usize] & mask
+                } else {
+                    0
+                };
+                let idx = y * self.width + x;
+                self.pixels[idx] = (old[idx] & !mask) | moved;
+            }
+        }
+    }
+}
@@ -0,0 +1,145 @@
+use std::cell::UnsafeCell;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Set on the shared index when the middle buffer holds a value the reader hasn't seen
+const FRESH: usize = 0b100;
+const INDEX_MASK: usize = 0b011;
+
+// Lock-free single producer, single consumer handoff. The writer and reader
+// each own one of three buffers and swap theirs with the shared middle one,
+// so the writer never waits and the reader always gets the latest complete value.
+pub struct TripleBuffer<T> {
+    buffers: [UnsafeCell<T>; 3],
+    middle: AtomicUsize,
+}
+
+// Each buffer is only ever reachable through the one index that owns it
+unsafe impl<T: Send> Sync for TripleBuffer<T> {}
+
+pub struct Writer<T> {
+    shared: Arc<TripleBuffer<T>>,
+    index: usize,
+}
+
+pub struct Reader<T> {
+    shared: Arc<TripleBuffer<T>>,
+    index: usize,
+}
+
+impl<T: Clone> TripleBuffer<T> {
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(initial: T) -> (Writer<T>, Reader<T>) {
+        let shared = Arc::new(TripleBuffer {
+            buffers: [
+                UnsafeCell::new(initial.clone()),
+                UnsafeCell::new(initial.clone()),
+                UnsafeCell::new(initial),
+            ],
+            middle: AtomicUsize::new(1),
+        });
+        let writer = Writer {
+            shared: shared.clone(),
+            index: 0,
+        };
+        let reader = Reader {
+            shared,
+            index: 2,
+        };
+        return (writer, reader);
+    }
+}
+
+impl<T> Writer<T> {
+    // The buffer to fill before the next publish, holding an older value
+    pub fn buffer(&mut self) -> &mut T {
+        unsafe { &mut *self.shared.buffers[self.index].get() }
+    }
+
+    pub fn publish(&mut self) {
+        let previous = self.shared.middle.swap(self.index | FRESH, Ordering::AcqRel);
+        self.index = previous & INDEX_MASK;
+    }
+}
+
+impl<T> Reader<T> {
+    // Returns the latest published value, or the last one read if nothing new arrived
+    pub fn read(&mut self) -> &T {
+        if self.shared.middle.load(Ordering::Acquire) & FRESH != 0 {
+            let previous = self.shared.middle.swap(self.index, Ordering::AcqRel);
+            self.index = previous & INDEX_MASK;
+        }
+        unsafe { &*self.shared.buffers[self.index].get() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn reads_the_initial_value_until_something_is_published() {
+        let (mut writer, mut reader) = TripleBuffer::new(0);
+        assert_eq!(*reader.read(), 0);
+        *writer.buffer() = 1;
+        assert_eq!(*reader.read(), 0);
+        writer.publish();
+        assert_eq!(*reader.read(), 1);
+    }
+
+    #[test]
+    fn reads_the_latest_of_several_publishes() {
+        let (mut writer, mut reader) = TripleBuffer::new(0);
+        for value in 1..=5 {
+            *writer.buffer() = value;
+            writer.publish();
+        }
+        assert_eq!(*reader.read(), 5);
+        *writer.buffer() = 6;
+        writer.publish();
+        *writer.buffer() = 7;
+        writer.publish();
+        assert_eq!(*reader.read(), 7);
+    }
+
+    #[test]
+    fn rereads_the_same_value_when_nothing_new_is_published() {
+        let (mut writer, mut reader) = TripleBuffer::new(0);
+        *writer.buffer() = 1;
+        writer.publish();
+        assert_eq!(*reader.read(), 1);
+        // Filling the writer's buffers without publishing leaves the reader's alone
+        for value in 2..5 {
+            *writer.buffer() = value;
+            assert_eq!(*reader.read(), 1);
+        }
+        writer.publish();
+        assert_eq!(*reader.read(), 4);
+        assert_eq!(*reader.read(), 4);
+    }
+
+    #[test]
+    fn reader_never_sees_a_torn_or_older_value_across_threads() {
+        const PUBLISHES: u32 = 200_000;
+        let (mut writer, mut reader) = TripleBuffer::new(vec![0u32; 64]);
+        let producer = thread::spawn(move || {
+            for value in 1..=PUBLISHES {
+                for item in writer.buffer().iter_mut() {
+                    *item = value;
+                }
+                writer.publish();
+            }
+        });
+
+        let mut last = 0;
+        while last < PUBLISHES {
+            let values = reader.read();
+            let value = values[0];
+            assert!(values.iter().all(|item| *item == value), "torn read of {}", value);
+            assert!(value >= last, "read {} after {}", value, last);
+            last = value;
+        }
+        producer.join().unwrap();
+    }
+}
@@ -0,0 +1,70 @@
+use super::canvas::Canvas;
+use super::font::{CHAR_WIDTH, LINE_HEIGHT};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Lists the files in a directory for picking a ROM with the arrow keys
+pub struct RomBrowser {
+    dir: PathBuf,
+    entries: Vec<PathBuf>,
+    selected: usize,
+}
+
+impl RomBrowser {
+    pub fn open(dir: &Path) -> io::Result<Self> {
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_file() {
+                entries.push(path);
+            }
+        }
+        entries.sort();
+        return Ok(RomBrowser {
+            dir: dir.to_path_buf(),
+            entries,
+            selected: 0,
+        });
+    }
+
+    // Moves the highlight by `delta` entries, stopping at either end
+    pub fn move_selection(&mut self, delta: isize) {
+        if self.entries.is_empty() {
+            return;
+        }
+        let selected = self.selected as isize + delta;
+        self.selected = selected.max(0).min(self.entries.len() as isize - 1) as usize;
+    }
+
+    pub fn selected(&self) -> Option<&Path> {
+        return self.entries.get(self.selected).map(|path| path.as_path());
+    }
+
+    // Draws the listing in the palette's first two colors, scrolled to keep
+    // the selection in view
+    pub fn draw(&self, canvas: &mut Canvas, colors: &[[u8; 4]; 4]) {
+        let (background, foreground) = (colors[0], colors[1]);
+        canvas.reset(canvas.width, canvas.height, background);
+        let title = format!("Open ROM from {}/ (Enter to load, Esc to cancel)", self.dir.display());
+        canvas.draw_text(2, 2, &title, foreground);
+        if self.entries.is_empty() {
+            canvas.draw_text(2, 2 + LINE_HEIGHT * 2, "No files found", foreground);
+            return;
+        }
+
+        let top = 2 + LINE_HEIGHT * 2;
+        let rows = (canvas.height.saturating_sub(top) / LINE_HEIGHT).max(1);
+        let first = (self.selected + 1).saturating_sub(rows);
+        for (i, path) in self.entries.iter().enumerate().skip(first).take(rows) {
+            let y = top + (i - first) * LINE_HEIGHT;
+            let name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+            if i == self.selected {
+                canvas.fill_rect(0, y - 1, canvas.width, LINE_HEIGHT, foreground);
+                canvas.draw_text(2 + CHAR_WIDTH, y, &name, background);
+            } else {
+                canvas.draw_text(2 + CHAR_WIDTH, y, &name, foreground);
+            }
+        }
+    }
+}
@@ -0,0 +1,66 @@
+use super::font::{self, GLYPH_HEIGHT, GLYPH_WIDTH, CHAR_WIDTH, LINE_HEIGHT};
+
+// Opacity of the boxes behind text drawn over the game
+pub const LABEL_ALPHA: u8 = 0xC0;
+
+// An RGBA image the window draws its own text and panels into before
+// uploading it as a texture
+pub struct Canvas {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+}
+
+impl Canvas {
+    pub fn new(width: usize, height: usize) -> Self {
+        Canvas {
+            width,
+            height,
+            pixels: vec![0; width * height * 4],
+        }
+    }
+
+    // Resizes if needed and fills with `color`
+    pub fn reset(&mut self, width: usize, height: usize, color: [u8; 4]) {
+        self.width = width;
+        self.height = height;
+        self.pixels.resize(width * height * 4, 0);
+        for pixel in self.pixels.chunks_mut(4) {
+            pixel.copy_from_slice(&color);
+        }
+    }
+
+    pub fn fill_rect(&mut self, x: usize, y: usize, width: usize, height: usize, color: [u8; 4]) {
+        for py in y..(y + height).min(self.height) {
+            for px in x..(x + width).min(self.width) {
+                let offset = (py * self.width + px) * 4;
+                self.pixels[offset..offset + 4].copy_from_slice(&color);
+            }
+        }
+    }
+
+    // Draws one line of text with its top left corner at (x, y), clipped to the canvas
+    pub fn draw_text(&mut self, x: usize, y: usize, text: &str, color: [u8; 4]) {
+        for (i, c) in text.chars().enumerate() {
+            let columns = font::glyph(c);
+            let cx = x + i * CHAR_WIDTH;
+            if cx >= self.width {
+                break;
+            }
+            for (col, bits) in columns.iter().enumerate().take(GLYPH_WIDTH) {
+                for row in 0..GLYPH_HEIGHT {
+                    if bits & (1 << row) != 0 {
+                        self.fill_rect(cx + col, y + row, 1, 1, color);
+                    }
+                }
+            }
+        }
+    }
+
+    // Text on a box one pixel larger than it on every side
+    pub fn draw_label(&mut self, x: usize, y: usize, text: &str, foreground: [u8; 4], background: [u8; 4]) {
+        let width = text.chars().count() * CHAR_WIDTH + 1;
+        self.fill_rect(x.saturating_sub(1), y.saturating_sub(1), width + 1, LINE_HEIGHT + 1, background);
+        self.draw_text(x, y, text, foreground);
+    }
+}
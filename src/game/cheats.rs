@@ -0,0 +1,146 @@
+use super::canvas::{Canvas, LABEL_ALPHA};
+use super::font::{CHAR_WIDTH, LINE_HEIGHT};
+use crate::cheat::{Cheat, CheatSearch, Comparison};
+use crate::platform::Platform;
+use piston::input::Key;
+
+// Width of the panel in characters
+const PANEL_COLUMNS: usize = 36;
+
+const HELP: [&str; 4] = [
+    "N new search  E/I/D equal/up/down",
+    "Up/Down pick  Enter freeze value",
+    "-/+ poke value  X clear cheats",
+    "S save  F11 close",
+];
+
+// What a key press in the cheat panel asks the window to do
+pub enum CheatAction {
+    None,
+    // Apply this list of cheats from now on
+    SetCheats(Vec<Cheat>),
+    Poke(u16, u8),
+    Save,
+}
+
+// Searches memory for values worth freezing and edits the ROM's cheat list.
+// Searches compare against the debugger snapshot, so they see memory as of
+// the last frame.
+pub struct CheatPanel {
+    search: Option<CheatSearch>,
+    selected: usize,
+}
+
+impl CheatPanel {
+    pub fn new() -> Self {
+        CheatPanel {
+            search: None,
+            selected: 0,
+        }
+    }
+
+    pub fn handle_key(&mut self, key: Key, ram: &[u8], platform: Platform, cheats: &[Cheat]) -> CheatAction {
+        let comparison = match key {
+            Key::E => Some(Comparison::Equal),
+            Key::I => Some(Comparison::Increased),
+            Key::D => Some(Comparison::Decreased),
+            _ => None,
+        };
+        if let Some(comparison) = comparison {
+            match self.search {
+                Some(ref mut search) => search.filter(ram, comparison),
+                None => self.search = Some(CheatSearch::new(ram, platform)),
+            }
+            self.selected = 0;
+            return CheatAction::None;
+        }
+
+        let selected = self.search.as_ref()
+            .and_then(|search| search.candidates().get(self.selected))
+            .map(|(addr, _)| *addr);
+        match key {
+            Key::N => {
+                self.search = Some(CheatSearch::new(ram, platform));
+                self.selected = 0;
+            },
+            Key::Up => self.selected = self.selected.saturating_sub(1),
+            Key::Down => {
+                let count = self.search.as_ref().map(|search| search.candidates().len()).unwrap_or(0);
+                self.selected = (self.selected + 1).min(count.saturating_sub(1));
+            },
+            Key::Return => {
+                if let Some(addr) = selected {
+                    let mut cheats: Vec<Cheat> = cheats.iter().filter(|cheat| cheat.addr != addr).cloned().collect();
+                    cheats.push(Cheat {
+                        addr,
+                        value: ram[addr as usize],
+                        description: String::new(),
+                    });
+                    return CheatAction::SetCheats(cheats);
+                }
+            },
+            Key::Minus | Key::Equals | Key::Plus => {
+                if let Some(addr) = selected {
+                    let value = ram[addr as usize];
+                    let value = if key == Key::Minus { value.wrapping_sub(1) } else { value.wrapping_add(1) };
+                    return CheatAction::Poke(addr, value);
+                }
+            },
+            Key::X => return CheatAction::SetCheats(Vec::new()),
+            Key::S => return CheatAction::Save,
+            _ => {},
+        }
+        return CheatAction::None;
+    }
+
+    // Draws the panel with its top right corner at (right, top)
+    pub fn draw(&self, canvas: &mut Canvas, right: usize, top: usize, ram: &[u8], cheats: &[Cheat], colors: &[[u8; 4]; 4]) {
+        let (foreground, mut background) = (colors[1], colors[0]);
+        background[3] = LABEL_ALPHA;
+        let width = PANEL_COLUMNS * CHAR_WIDTH;
+        let x = right.saturating_sub(width);
+        let rows = canvas.height.saturating_sub(top) / LINE_HEIGHT;
+        canvas.fill_rect(x, top, width, rows * LINE_HEIGHT, background);
+
+        let mut lines = vec!["Cheats".to_string()];
+        lines.extend(HELP.iter().map(|line| line.to_string()));
+        lines.push(String::new());
+        if cheats.is_empty() {
+            lines.push("No cheats".to_string());
+        }
+        for cheat in cheats {
+            lines.push(format!("{:04X} = {:02X} {}", cheat.addr, cheat.value, cheat.description));
+        }
+        lines.push(String::new());
+
+        let mut y = top + 1;
+        for line in lines.iter().take(rows) {
+            canvas.draw_text(x + 2, y, line, foreground);
+            y += LINE_HEIGHT;
+        }
+        let rows = rows.saturating_sub(lines.len() + 1);
+        let search = match self.search {
+            Some(ref search) => search,
+            None => {
+                canvas.draw_text(x + 2, y, "Press N to start a search", foreground);
+                return;
+            },
+        };
+
+        let candidates = search.candidates();
+        canvas.draw_text(x + 2, y, &format!("{} addresses", candidates.len()), foreground);
+        y += LINE_HEIGHT;
+        // Scroll to keep the selection in view
+        let first = (self.selected + 1).saturating_sub(rows);
+        for (i, (addr, last)) in candidates.iter().enumerate().skip(first).take(rows) {
+            let line = format!("{:04X}  now {:02X}  was {:02X}", addr, ram[*addr as usize], last);
+            if i == self.selected {
+                canvas.fill_rect(x, y - 1, width, LINE_HEIGHT, foreground);
+                canvas.draw_text(x + 2, y, &line, colors[0]);
+            } else {
+                canvas.draw_text(x + 2, y, &line, foreground);
+            }
+            y += LINE_HEIGHT;
+        }
+    }
+}
@@ -0,0 +1,191 @@
+use super::canvas::Canvas;
+use super::font::{CHAR_WIDTH, LINE_HEIGHT};
+use crate::interpreter::{self, Command, Snapshot, Stats};
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+
+// Bytes per row of the memory view
+const MEMORY_ROW: usize = 16;
+// Space around the edges of the panels
+const MARGIN: usize = 2;
+
+// Panels showing the machine state next to the game, laid out on the
+// canvas with the game in the top left, memory under it and the CPU state,
+// stack and disassembly on the right:
+//
+//     +-----------+---------------+
+//     |           | Step Continue |
+//     |   game    | registers     |
+//     |           | stack         |
+//     +-----------+ disassembly   |
+//     |  memory   |               |
+//     +-----------+---------------+
+pub struct Debugger {
+    snapshot: Arc<Mutex<Snapshot>>,
+    // First address in the memory view, or None to follow I
+    memory_addr: Option<usize>,
+    // Clickable areas from the last draw, as x, y, width and height on the canvas
+    buttons: Vec<([usize; 4], Command)>,
+}
+
+impl Debugger {
+    pub fn new(snapshot: Arc<Mutex<Snapshot>>) -> Self {
+        Debugger {
+            snapshot,
+            memory_addr: None,
+            buttons: Vec::new(),
+        }
+    }
+
+    // Where the game goes on a canvas of this size, as x, y, width and height
+    pub fn game_area(&self, width: usize, height: usize) -> [usize; 4] {
+        let game_width = width * 3 / 5;
+        return [0, 0, game_width, (game_width / 2).min(height)];
+    }
+
+    // Scrolls the memory view by whole rows, leaving it where it is from then on
+    pub fn scroll_memory(&mut self, rows: isize) {
+        let snapshot = self.snapshot.lock().unwrap();
+        let start = self.memory_addr.unwrap_or_else(|| follow_addr(snapshot.i_reg));
+        let last_row = snapshot.ram.len().saturating_sub(MEMORY_ROW) as isize;
+        let addr = (start as isize + rows * MEMORY_ROW as isize).max(0).min(last_row);
+        self.memory_addr = Some(addr as usize);
+    }
+
+    // The memory view follows I again
+    pub fn follow_i(&mut self) {
+        self.memory_addr = None;
+    }
+
+    // Returns what the button at canvas position (x, y) does, if there is one
+    pub fn click(&self, x: usize, y: usize) -> Option<Command> {
+        self.buttons.iter()
+            .find(|(area, _)| x >= area[0] && x < area[0] + area[2] && y >= area[1] && y < area[1] + area[3])
+            .map(|(_, command)| command.clone())
+    }
+
+    pub fn draw(&mut self, canvas: &mut Canvas, stats: &Stats, colors: &[[u8; 4]; 4]) {
+        let (background, foreground, highlight) = (colors[0], colors[1], colors[2]);
+        canvas.reset(canvas.width, canvas.height, background);
+        let snapshot = self.snapshot.lock().unwrap().clone();
+        let game = self.game_area(canvas.width, canvas.height);
+
+        // Right column, top to bottom
+        let x = game[2] + CHAR_WIDTH;
+        let mut y = MARGIN;
+        let paused = stats.paused.load(Ordering::Relaxed);
+        self.buttons.clear();
+        let step_width = draw_button(canvas, x, y, "Step F10", foreground, background);
+        self.buttons.push(([x, y, step_width, LINE_HEIGHT + 2], Command::Step));
+        let label = if paused { "Continue F8" } else { "Break F8" };
+        let button_x = x + step_width + CHAR_WIDTH;
+        let continue_width = draw_button(canvas, button_x, y, label, foreground, background);
+        self.buttons.push(([button_x, y, continue_width, LINE_HEIGHT + 2], Command::TogglePause));
+        y += LINE_HEIGHT * 2;
+
+        let status = if stats.halted.load(Ordering::Relaxed) {
+            "HALTED"
+        } else if paused {
+            "PAUSED"
+        } else if stats.waiting_for_key.load(Ordering::Relaxed) {
+            "WAITING FOR KEY"
+        } else {
+            "RUNNING"
+        };
+        canvas.draw_text(x, y, status, highlight);
+        y += LINE_HEIGHT;
+        if let Some(ref error) = *stats.error.lock().unwrap() {
+            canvas.draw_text(x, y, error, highlight);
+            y += LINE_HEIGHT;
+        }
+        if let Some(ref reason) = *stats.break_reason.lock().unwrap() {
+            canvas.draw_text(x, y, reason, highlight);
+            y += LINE_HEIGHT;
+        }
+        y += LINE_HEIGHT / 2;
+
+        for row in 0..4 {
+            let line: Vec<String> = (0..4)
+                .map(|col| row * 4 + col)
+                .map(|reg| format!("V{:X} {:02X}", reg, snapshot.registers[reg]))
+                .collect();
+            canvas.draw_text(x, y, &line.join("  "), foreground);
+            y += LINE_HEIGHT;
+        }
+        canvas.draw_text(x, y, &format!("PC {:04X}  I  {:04X}", snapshot.pc, snapshot.i_reg), foreground);
+        y += LINE_HEIGHT;
+        canvas.draw_text(x, y, &format!("DT {:02X}    ST {:02X}", snapshot.dt_reg, snapshot.st_reg), foreground);
+        y += LINE_HEIGHT + LINE_HEIGHT / 2;
+
+        // Most recent return address first
+        let stack: Vec<String> = snapshot.stack.iter().rev().map(|addr| format!("{:04X}", addr)).collect();
+        canvas.draw_text(x, y, &format!("Stack ({})", stack.len()), foreground);
+        y += LINE_HEIGHT;
+        for entries in stack.chunks(5).take(2) {
+            canvas.draw_text(x, y, &entries.join(" "), foreground);
+            y += LINE_HEIGHT;
+        }
+        y += LINE_HEIGHT / 2;
+
+        // Disassembly from a few instructions before PC to the bottom
+        let rows = canvas.height.saturating_sub(y + MARGIN) / LINE_HEIGHT;
+        let mut addr = snapshot.pc.saturating_sub(2 * (rows / 4));
+        for _ in 0..rows {
+            if addr + 1 >= snapshot.ram.len() {
+                break;
+            }
+            let (text, length) = interpreter::disassemble(&snapshot.ram, snapshot.coverage.as_deref(), snapshot.platform, addr);
+            let word = ((snapshot.ram[addr] as u16) << 8) | snapshot.ram[addr + 1] as u16;
+            let line = format!("{} {:04X} {:04X} {}", if addr == snapshot.pc { ">" } else { " " }, addr, word, text);
+            if addr == snapshot.pc {
+                canvas.fill_rect(x, y - 1, canvas.width.saturating_sub(x), LINE_HEIGHT, foreground);
+                canvas.draw_text(x, y, &line, background);
+            } else {
+                canvas.draw_text(x, y, &line, foreground);
+            }
+            y += LINE_HEIGHT;
+            addr += length;
+        }
+
+        // Memory under the game, with the byte at I picked out
+        let mut y = game[3] + LINE_HEIGHT;
+        let following = if self.memory_addr.is_some() { "" } else { " (following I)" };
+        canvas.draw_text(MARGIN, y, &format!("Memory{}", following), foreground);
+        y += LINE_HEIGHT;
+        let rows = canvas.height.saturating_sub(y + MARGIN) / LINE_HEIGHT;
+        let start = self.memory_addr.unwrap_or_else(|| follow_addr(snapshot.i_reg));
+        for row in 0..rows {
+            let row_addr = start + row * MEMORY_ROW;
+            if row_addr >= snapshot.ram.len() {
+                break;
+            }
+            canvas.draw_text(MARGIN, y, &format!("{:04X}", row_addr), foreground);
+            for col in 0..MEMORY_ROW {
+                let addr = row_addr + col;
+                let byte_x = MARGIN + (5 + col * 3) * CHAR_WIDTH;
+                let text = format!("{:02X}", snapshot.ram[addr]);
+                if addr == snapshot.i_reg as usize {
+                    canvas.fill_rect(byte_x - 1, y - 1, 2 * CHAR_WIDTH + 1, LINE_HEIGHT, foreground);
+                    canvas.draw_text(byte_x, y, &text, background);
+                } else {
+                    canvas.draw_text(byte_x, y, &text, foreground);
+                }
+            }
+            y += LINE_HEIGHT;
+        }
+    }
+}
+
+// The memory view starts a couple of rows before the one I points into
+fn follow_addr(i_reg: u16) -> usize {
+    return (i_reg as usize & !(MEMORY_ROW - 1)).saturating_sub(2 * MEMORY_ROW);
+}
+
+// Draws a framed label and returns its width
+fn draw_button(canvas: &mut Canvas, x: usize, y: usize, label: &str, foreground: [u8; 4], background: [u8; 4]) -> usize {
+    let width = label.chars().count() * CHAR_WIDTH + 3;
+    canvas.fill_rect(x, y, width, LINE_HEIGHT + 2, foreground);
+    canvas.fill_rect(x + 1, y + 1, width - 2, LINE_HEIGHT, background);
+    canvas.draw_text(x + 2, y + 2, label, foreground);
+    return width;
+}
@@ -3,20 +3,50 @@ extern crate graphics;
 extern crate opengl_graphics;
 extern crate piston;
 
+mod browser;
+mod canvas;
+mod cheats;
+mod debugger;
+mod font;
+mod overlay;
+mod persistence;
+
+pub use persistence::Persistence;
+
 use glutin_window::GlutinWindow as Window;
-use opengl_graphics::{GlGraphics, OpenGL};
+use opengl_graphics::{CreateTexture, Filter, Format, GlGraphics, OpenGL, Texture, TextureSettings, UpdateTexture};
 use piston::event_loop::{EventSettings, Events};
-use piston::input::{RenderArgs, RenderEvent, UpdateArgs, UpdateEvent};
-use piston::window::WindowSettings;
-use crate::game::piston::{PressEvent, ReleaseEvent, Button, Key};
+use piston::input::{Button, Event, FileDrag, FocusEvent, Input, Key, MouseButton, MouseCursorEvent, MouseScrollEvent, PressEvent, ReleaseEvent, RenderArgs, RenderEvent};
+use piston::window::{AdvancedWindow, Window as _, WindowSettings};
+use std::path::{Path, PathBuf};
 use std::sync::Mutex;
 use std::sync::Arc;
-use std::sync::atomic::AtomicBool;
-use std::{thread, time};
-use device_query::{DeviceQuery, DeviceState, Keycode};
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::Sender;
+use crate::cheat::{self, Cheat};
+use crate::config::Config;
+use crate::display::FrameReader;
+use crate::gamepad::{GamepadInput, GamepadMapping};
+use crate::interpreter::{Command, Snapshot, Stats};
+use crate::keymap::{self, Keymap};
+use crate::palette::Palette;
+use crate::platform::Overrides;
+use crate::rom;
+use crate::romdb::{self, RomDatabase};
+use browser::RomBrowser;
+use canvas::Canvas;
+use cheats::{CheatAction, CheatPanel};
+use debugger::Debugger;
+use overlay::Overlay;
+use persistence::PersistenceFilter;
+
+// Where the ROM browser looks for games
+const ROM_DIR: &str = "roms";
+// Window pixels per canvas pixel when drawing text
+const TEXT_SCALE: f64 = 2.0;
+// The window grows to at least this size to fit the debugger panels
+const DEBUGGER_WINDOW_SIZE: [f64; 2] = [1280.0, 720.0];
 
-const WIDTH: usize = 80;
-const HEIGHT: usize = 64;
 
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub enum KeyState {
@@ -24,154 +54,587 @@ pub enum KeyState {
     Released,
 }
 
+// Where a key press came from. Each source holds keys independently, so
+// letting go of a keyboard key doesn't release the same key held on a gamepad.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum KeySource {
+    Keyboard = 0,
+    Gamepad = 1,
+}
+
 pub struct GameState {
-    display: [[bool; HEIGHT]; WIDTH],
-    keys: [KeyState;16],
+    // Bitmask of the keys held by each source
+    held: [u16; 2],
+    // Bitmasks of keys that went down or up since the edges were last taken,
+    // so presses shorter than the interpreter's polling aren't lost
+    pressed_edges: u16,
+    released_edges: u16,
 }
 
 pub struct Game {
     gl: GlGraphics,
     window: Window,
     state: Arc<Mutex<GameState>>,
+    frames: FrameReader,
+    stats: Arc<Stats>,
+    keymap: Keymap,
+    // The controller, if any, whose mapping follows the loaded ROM
+    gamepad: Option<Arc<Mutex<GamepadInput>>>,
+    // Host keys currently held down in the window
+    held_keys: Vec<String>,
+    palettes: Vec<Palette>,
+    palette: usize,
+    persistence: PersistenceFilter,
+    // Scratch buffer reused between frames
+    pixels: Vec<u8>,
+    texture: Option<Texture>,
+    controls: Option<Sender<Command>>,
+    // Where Reload reads the ROM from
+    rom_path: PathBuf,
+    // Per-ROM keymaps are looked up here when switching games
+    config: Config,
+    browser: Option<RomBrowser>,
+    overlay: Overlay,
+    debug_snapshot: Option<Arc<Mutex<Snapshot>>>,
+    debugger: Option<Debugger>,
+    // The window size to go back to when the debugger closes
+    undebugged_size: [f64; 2],
+    mouse: [f64; 2],
+    cheat_panel: Option<CheatPanel>,
+    cheats: Vec<Cheat>,
+    // Where the running ROM's cheats are saved
+    cheat_path: PathBuf,
+    // Settings for ROMs loaded from the window come from here, then the command line
+    database: Option<RomDatabase>,
+    overrides: Overrides,
+    // Shared by the ROM browser and the overlay, whichever is showing
+    canvas: Canvas,
+    canvas_texture: Option<Texture>,
 }
 
 impl GameState {
     pub fn new() -> Self {
         GameState {
-            display: [[false; HEIGHT as usize]; WIDTH as usize],
-            keys: [KeyState::Released; 16],
+            held: [0; 2],
+            pressed_edges: 0,
+            released_edges: 0,
         }
     }
 
-    pub fn set_pixel(&mut self, x: usize, y: usize, pixel_state: bool) {
-        self.display[x % WIDTH][y % HEIGHT] = pixel_state;
+    pub fn get_key_state(&self, key_id: u8) -> KeyState {
+        if self.keys_down() & (1 << (key_id & 0x0F)) != 0 {
+            KeyState::Pressed
+        } else {
+            KeyState::Released
+        }
     }
 
-    pub fn get_pixel(&self, x: usize, y: usize) -> bool {
-        self.display[x % WIDTH][y % HEIGHT]
+    pub fn set_key_state(&mut self, source: KeySource, key_id: u8, key_state: KeyState) {
+        let bit = 1 << (key_id & 0x0F);
+        let before = self.keys_down();
+        match key_state {
+            KeyState::Pressed => self.held[source as usize] |= bit,
+            KeyState::Released => self.held[source as usize] &= !bit,
+        }
+        let after = self.keys_down();
+        self.pressed_edges |= after & !before;
+        self.released_edges |= before & !after;
     }
 
-    pub fn clear_display(&mut self) {
-        self.display = [[false; HEIGHT as usize]; WIDTH as usize];
+    fn keys_down(&self) -> u16 {
+        self.held.iter().fold(0, |acc, held| acc | held)
     }
 
-    pub fn get_key_state(&self, key_id: u8) -> KeyState {
-        self.keys[(key_id) as usize]
+    // Returns the (pressed, released) edge masks and starts collecting afresh
+    pub fn take_key_edges(&mut self) -> (u16, u16) {
+        let edges = (self.pressed_edges, self.released_edges);
+        self.pressed_edges = 0;
+        self.released_edges = 0;
+        return edges;
     }
 }
 
 impl Game {
-    pub fn new(title: String, state: Arc<Mutex<GameState>>) -> Self {
+    pub fn new(title: String, state: Arc<Mutex<GameState>>, mut frames: FrameReader, stats: Arc<Stats>) -> Self {
         // Change this to OpenGL::V2_1 if not working.
         let opengl = OpenGL::V3_2;
 
-        let window = WindowSettings::new(title, [800, 640])
-        .exit_on_esc(true)
+        // Size the window to the platform's standard resolution
+        let size = {
+            let frame = frames.read();
+            [(frame.width * 10) as u32, (frame.height * 10) as u32]
+        };
+        let window = WindowSettings::new(title, size)
+        // Escape closes the ROM browser first, so quitting is handled in start
+        .exit_on_esc(false)
         .graphics_api(opengl)
         .build()
         .unwrap();
 
         return Game {
-            state: state,
+            state,
             gl: GlGraphics::new(opengl),
-            window: window,
+            window,
+            frames,
+            stats,
+            keymap: Keymap::new(),
+            gamepad: None,
+            held_keys: Vec::new(),
+            palettes: Palette::builtins(),
+            palette: 0,
+            persistence: PersistenceFilter::new(Persistence::Off),
+            pixels: Vec::new(),
+            texture: None,
+            controls: None,
+            rom_path: PathBuf::new(),
+            config: Config::empty(),
+            browser: None,
+            overlay: Overlay::new(),
+            debug_snapshot: None,
+            debugger: None,
+            undebugged_size: [size[0] as f64, size[1] as f64],
+            mouse: [0.0, 0.0],
+            cheat_panel: None,
+            cheats: Vec::new(),
+            cheat_path: PathBuf::new(),
+            database: None,
+            overrides: Overrides::default(),
+            canvas: Canvas::new(0, 0),
+            canvas_texture: None,
+        }
+    }
+
+    // Replaces the palettes P cycles through, starting from the one at `selected`
+    pub fn set_palettes(&mut self, palettes: Vec<Palette>, selected: usize) {
+        if !palettes.is_empty() {
+            self.palette = selected.min(palettes.len() - 1);
+            self.palettes = palettes;
         }
     }
 
+    pub fn set_persistence(&mut self, mode: Persistence) {
+        self.persistence = PersistenceFilter::new(mode);
+    }
+
+    pub fn set_keymap(&mut self, keymap: Keymap) {
+        self.keymap = keymap;
+    }
+
+    pub fn set_gamepad(&mut self, gamepad: Arc<Mutex<GamepadInput>>) {
+        self.gamepad = Some(gamepad);
+    }
+
+    pub fn set_config(&mut self, config: Config) {
+        self.config = config;
+    }
+
+    // The running ROM's cheats and the file they are saved to
+    pub fn set_cheats(&mut self, cheats: Vec<Cheat>, path: PathBuf) {
+        self.cheats = cheats;
+        self.cheat_path = path;
+    }
+
+    pub fn set_rom_database(&mut self, database: RomDatabase, overrides: Overrides) {
+        self.database = Some(database);
+        self.overrides = overrides;
+    }
+
+    // Lets F7 open the debugger panels, showing state from `snapshot`
+    pub fn set_debug_snapshot(&mut self, snapshot: Arc<Mutex<Snapshot>>) {
+        self.debug_snapshot = Some(snapshot);
+    }
+
+    // Lets the window pause, reset and reload the interpreter, and stop it when closed
+    pub fn set_controls(&mut self, controls: Sender<Command>, rom_path: PathBuf) {
+        self.controls = Some(controls);
+        self.rom_path = rom_path;
+    }
+
     pub fn start(&mut self) {
         let mut events = Events::new(EventSettings::new());
-        let ds = DeviceState::new();
-        let key_list = vec!(Keycode::X, Keycode::Key1, Keycode::Key2, Keycode::Key3, 
-            Keycode::Q, Keycode::W, Keycode::E, Keycode::A, Keycode::S, Keycode::D, 
-            Keycode::Z, Keycode::C, Keycode::Key4, Keycode::R, Keycode::F, Keycode::V);
         while let Some(e) = events.next({
             // scoping so lock gets released
             &mut self.window
         }) {
             if let Some(args) = e.render_args() {
+                // The interpreter paused itself, so show where
+                if self.stats.break_requested.swap(false, Ordering::Relaxed) && self.debugger.is_none() {
+                    self.toggle_debugger();
+                }
                 self.render(&args);
             }
-            let key_presses: Vec<Keycode> = ds.get_keys();
-            // let mut keys = self.state.lock().unwrap().keys;
-            for key in key_presses.iter() {
-                match key {
-                    Keycode::X => {self.state.lock().unwrap().keys[0] = KeyState::Pressed}
-                    Keycode::Key1 => {self.state.lock().unwrap().keys[1] = KeyState::Pressed}
-                    Keycode::Key2 => {self.state.lock().unwrap().keys[2] = KeyState::Pressed}
-                    Keycode::Key3 => {self.state.lock().unwrap().keys[3] = KeyState::Pressed}
-                    Keycode::Q => {self.state.lock().unwrap().keys[4] = KeyState::Pressed}
-                    Keycode::W => {self.state.lock().unwrap().keys[5] = KeyState::Pressed}
-                    Keycode::E => {self.state.lock().unwrap().keys[6] = KeyState::Pressed}
-                    Keycode::A => {self.state.lock().unwrap().keys[7] = KeyState::Pressed}
-                    Keycode::S => {self.state.lock().unwrap().keys[8] = KeyState::Pressed}
-                    Keycode::D => {self.state.lock().unwrap().keys[9] = KeyState::Pressed}
-                    Keycode::Z => {self.state.lock().unwrap().keys[10] = KeyState::Pressed}
-                    Keycode::C => {self.state.lock().unwrap().keys[11] = KeyState::Pressed}
-                    Keycode::Key4 => {self.state.lock().unwrap().keys[12] = KeyState::Pressed}
-                    Keycode::R => {self.state.lock().unwrap().keys[13] = KeyState::Pressed}
-                    Keycode::F => {self.state.lock().unwrap().keys[14] = KeyState::Pressed}
-                    Keycode::V => {self.state.lock().unwrap().keys[15] = KeyState::Pressed}
-                    _ => {}
+            if let Event::Input(Input::FileDrag(FileDrag::Drop(ref path)), _) = e {
+                self.browser = None;
+                self.load_rom(path);
+            }
+            // Releases that happen while another window has focus never arrive
+            if e.focus_args() == Some(false) {
+                self.release_keys();
+            }
+            if let Some(position) = e.mouse_cursor_args() {
+                self.mouse = position;
+            }
+            if let Some(Button::Mouse(MouseButton::Left)) = e.press_args() {
+                let clicked = self.debugger.as_ref().and_then(|debugger| {
+                    debugger.click((self.mouse[0] / TEXT_SCALE) as usize, (self.mouse[1] / TEXT_SCALE) as usize)
+                });
+                if let Some(command) = clicked {
+                    self.send(command);
                 }
             }
-            for key in key_list.iter() {
-                if !key_presses.contains(&key) {
-                    match key {
-                        Keycode::X => {self.state.lock().unwrap().keys[0] = KeyState::Released}
-                        Keycode::Key1 => {self.state.lock().unwrap().keys[1] = KeyState::Released}
-                        Keycode::Key2 => {self.state.lock().unwrap().keys[2] = KeyState::Released}
-                        Keycode::Key3 => {self.state.lock().unwrap().keys[3] = KeyState::Released}
-                        Keycode::Q => {self.state.lock().unwrap().keys[4] = KeyState::Released}
-                        Keycode::W => {self.state.lock().unwrap().keys[5] = KeyState::Released}
-                        Keycode::E => {self.state.lock().unwrap().keys[6] = KeyState::Released}
-                        Keycode::A => {self.state.lock().unwrap().keys[7] = KeyState::Released}
-                        Keycode::S => {self.state.lock().unwrap().keys[8] = KeyState::Released}
-                        Keycode::D => {self.state.lock().unwrap().keys[9] = KeyState::Released}
-                        Keycode::Z => {self.state.lock().unwrap().keys[10] = KeyState::Released}
-                        Keycode::C => {self.state.lock().unwrap().keys[11] = KeyState::Released}
-                        Keycode::Key4 => {self.state.lock().unwrap().keys[12] = KeyState::Released}
-                        Keycode::R => {self.state.lock().unwrap().keys[13] = KeyState::Released}
-                        Keycode::F => {self.state.lock().unwrap().keys[14] = KeyState::Released}
-                        Keycode::V => {self.state.lock().unwrap().keys[15] = KeyState::Released}
-                        _ => {}
-                    }
+            if let Some(scroll) = e.mouse_scroll_args() {
+                if let Some(ref mut debugger) = self.debugger {
+                    debugger.scroll_memory(-scroll[1].signum() as isize);
                 }
             }
-            thread::sleep(time::Duration::from_millis(1));
+            if let Some(Button::Keyboard(key)) = e.press_args() {
+                if self.browser.is_some() {
+                    self.browse(key);
+                    continue;
+                }
+                if self.cheat_panel.is_some() && key != Key::F11 && key != Key::Escape {
+                    self.edit_cheats(key);
+                    continue;
+                }
+                // Keys used as hotkeys don't also press keypad keys bound to them
+                if self.hotkey(key) {
+                    continue;
+                }
+                let name = keymap::normalize(&format!("{:?}", key));
+                if !self.held_keys.contains(&name) {
+                    self.held_keys.push(name.clone());
+                    self.update_keypad(&name);
+                }
+            }
+            if let Some(Button::Keyboard(key)) = e.release_args() {
+                let name = keymap::normalize(&format!("{:?}", key));
+                self.held_keys.retain(|held| *held != name);
+                self.update_keypad(&name);
+            }
+        }
+        self.send(Command::Quit);
+    }
+
+    fn send(&self, command: Command) {
+        if let Some(ref controls) = self.controls {
+            // The interpreter only stops listening once it has quit
+            let _ = controls.send(command);
+        }
+    }
+
+    // Reads the ROM from disk again, picking up changes since it was loaded
+    fn reload(&mut self) {
+        let path = self.rom_path.clone();
+        self.load_rom(&path);
+    }
+
+    // Swaps the running program for the ROM at `path`, along with its
+    // settings, keymap and cheats
+    fn load_rom(&mut self, path: &Path) {
+        if self.controls.is_none() {
+            return;
+        }
+        let rom = match rom::load(path) {
+            Ok(rom) => rom,
+            Err(e) => {
+                self.report(format!("Couldn't load {}: {}", path.display(), e));
+                return;
+            }
+        };
+        let rom_name = path.file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let entry = self.database.as_ref().and_then(|database| database.lookup(&rom.hash)).cloned();
+        let settings = romdb::resolve(&rom.overrides, entry.as_ref(), &self.overrides);
+        if let Err(e) = settings.platform.check_program_size(rom.bytes.len()) {
+            self.report(format!("Couldn't load {}: {}", rom_name, e));
+            return;
+        }
+        if let Some(colors) = rom.colors {
+            self.palette = Palette::insert(&mut self.palettes, Palette::cartridge(colors));
+        }
+        let suggested_keymap = entry.as_ref().and_then(|entry| entry.keymap.as_ref());
+        match Keymap::load(&self.config, &rom_name, suggested_keymap) {
+            Ok(keymap) => self.keymap = keymap,
+            Err(e) => self.report(format!("Invalid keymap for {}: {}", rom_name, e)),
+        }
+        if let Some(ref gamepad) = self.gamepad {
+            match GamepadMapping::load(&self.config, &rom_name) {
+                Ok(mapping) => gamepad.lock().unwrap().set_mapping(mapping, &mut self.state.lock().unwrap()),
+                Err(e) => self.report(format!("Invalid gamepad mapping for {}: {}", rom_name, e)),
+            }
+        }
+        self.cheat_path = cheat::path_for(&cheat::dir(&self.config), &rom.hash);
+        self.cheats = match cheat::load(&self.cheat_path) {
+            Ok(cheats) => cheats,
+            Err(e) => {
+                self.report(format!("Couldn't load cheats: {}", e));
+                Vec::new()
+            }
+        };
+        self.release_keys();
+        self.send(Command::Load(rom.bytes, settings));
+        if !self.cheats.is_empty() {
+            self.send(Command::SetCheats(self.cheats.clone()));
+        }
+        let name = entry.as_ref().and_then(|entry| entry.display_name()).unwrap_or_else(|| rom_name.clone());
+        self.overlay.notify(format!("Loaded {}", name));
+        self.window.set_title(rom_name);
+        self.rom_path = path.to_path_buf();
+    }
+
+    fn open_browser(&mut self) {
+        match RomBrowser::open(Path::new(ROM_DIR)) {
+            Ok(browser) => {
+                // Keys held now would otherwise stay down until pressed again
+                self.release_keys();
+                self.browser = Some(browser);
+            },
+            Err(e) => self.report(format!("Couldn't list {}: {}", ROM_DIR, e)),
+        }
+    }
+
+    fn toggle_debugger(&mut self) {
+        if self.debugger.take().is_some() {
+            self.window.set_size(self.undebugged_size);
+            return;
+        }
+        let snapshot = match self.debug_snapshot {
+            Some(ref snapshot) => snapshot.clone(),
+            None => return,
+        };
+        let size = self.window.size();
+        self.undebugged_size = [size.width, size.height];
+        self.window.set_size([size.width.max(DEBUGGER_WINDOW_SIZE[0]), size.height.max(DEBUGGER_WINDOW_SIZE[1])]);
+        self.debugger = Some(Debugger::new(snapshot));
+    }
+
+    fn toggle_cheat_panel(&mut self) {
+        if self.cheat_panel.take().is_some() || self.debug_snapshot.is_none() {
+            return;
+        }
+        self.release_keys();
+        self.cheat_panel = Some(CheatPanel::new());
+    }
+
+    // Keys go to the cheat panel instead of the keypad while it is open
+    fn edit_cheats(&mut self, key: Key) {
+        let action = {
+            let snapshot = self.debug_snapshot.as_ref().unwrap().lock().unwrap();
+            self.cheat_panel.as_mut().unwrap().handle_key(key, &snapshot.ram, snapshot.platform, &self.cheats)
+        };
+        match action {
+            CheatAction::None => {},
+            CheatAction::SetCheats(cheats) => {
+                self.overlay.notify(format!("{} cheats active", cheats.len()));
+                self.cheats = cheats;
+                self.send(Command::SetCheats(self.cheats.clone()));
+            },
+            CheatAction::Poke(addr, value) => self.send(Command::Poke(addr, value)),
+            CheatAction::Save => {
+                match cheat::save(&self.cheat_path, &self.cheats) {
+                    Ok(()) => self.overlay.notify(format!("Saved cheats to {}", self.cheat_path.display())),
+                    Err(e) => self.report(format!("Couldn't save cheats: {}", e)),
+                }
+            },
+        }
+    }
+
+    fn report(&mut self, message: String) {
+        eprintln!("{}", message);
+        self.overlay.notify(message);
+    }
+
+    // Keys go to the ROM browser instead of the keypad while it is open
+    fn browse(&mut self, key: Key) {
+        let browser = self.browser.as_mut().unwrap();
+        match key {
+            Key::Up => browser.move_selection(-1),
+            Key::Down => browser.move_selection(1),
+            Key::PageUp => browser.move_selection(-10),
+            Key::PageDown => browser.move_selection(10),
+            Key::Return => {
+                let path = browser.selected().map(|path| path.to_path_buf());
+                self.browser = None;
+                if let Some(path) = path {
+                    self.load_rom(&path);
+                }
+            },
+            Key::Escape | Key::F4 => self.browser = None,
+            _ => {},
+        }
+    }
+
+    // Runs the window action bound to `key`, returning false if it has none
+    fn hotkey(&mut self, key: Key) -> bool {
+        match key {
+            Key::Escape if self.cheat_panel.is_some() => self.cheat_panel = None,
+            Key::Escape => self.window.set_should_close(true),
+            Key::P => {
+                self.palette = (self.palette + 1) % self.palettes.len();
+                let message = format!("Palette {}", self.palettes[self.palette].name);
+                self.overlay.notify(message);
+            },
+            Key::F1 => {
+                let paused = self.stats.paused.load(Ordering::Relaxed);
+                self.overlay.notify(if paused { "Resumed" } else { "Paused" }.to_string());
+                self.send(Command::TogglePause);
+            },
+            Key::F2 => {
+                self.overlay.notify("Reset".to_string());
+                self.send(Command::Reset);
+            },
+            Key::F3 => self.reload(),
+            Key::F4 => self.open_browser(),
+            Key::F5 => self.overlay.toggle(),
+            Key::F6 => {
+                let turbo = self.stats.turbo.load(Ordering::Relaxed);
+                self.overlay.notify(if turbo { "Normal speed" } else { "Turbo" }.to_string());
+                self.send(Command::ToggleTurbo);
+            },
+            Key::F7 => self.toggle_debugger(),
+            Key::F8 => {
+                if self.debugger.is_some() {
+                    self.send(Command::TogglePause);
+                }
+            },
+            Key::F9 => {
+                if let Some(ref mut debugger) = self.debugger {
+                    debugger.follow_i();
+                }
+            },
+            Key::F10 => {
+                if self.debugger.is_some() {
+                    self.send(Command::Step);
+                }
+            },
+            Key::F11 => self.toggle_cheat_panel(),
+            _ => return false,
+        }
+        return true;
+    }
+
+    fn release_keys(&mut self) {
+        let held_keys = std::mem::take(&mut self.held_keys);
+        for name in held_keys.iter() {
+            self.update_keypad(name);
+        }
+    }
+
+    // A keypad key stays down while any host key bound to it is held
+    fn update_keypad(&mut self, host_key: &str) {
+        let mut state = self.state.lock().unwrap();
+        for key_id in self.keymap.lookup(host_key) {
+            let held = self.keymap.host_keys(key_id).iter().any(|key| self.held_keys.contains(key));
+            let key_state = if held { KeyState::Pressed } else { KeyState::Released };
+            state.set_key_state(KeySource::Keyboard, key_id, key_state);
         }
     }
 
     fn render(&mut self, args: &RenderArgs) {
         use graphics::*;
 
-        const WHITE: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
-        const BLACK: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
-
-        for i in 0..WIDTH {
-            let x = args.window_size[0] / WIDTH as f64 * i as f64;
-            for j in 0..HEIGHT {
-                let y = args.window_size[1] / HEIGHT as f64 * j as f64;
-
-                let square = rectangle::square(0.0, 0.0, 20.0);
-                
-                {
-                    let pixel_state = self.state.lock().unwrap().get_pixel(i, j);
-                    self.gl.draw(args.viewport(), |c, gl| {
-                        let color: [f32; 4];
-                        if pixel_state {
-                            color = WHITE;
-                        } else {
-                            color = BLACK;
-                        }
-
-                        let transform = c
-                            .transform
-                            .trans(x, y);
-                        
-                        rectangle(color, square, transform, gl);
-                    });
-                } 
-            }
+        let frame = self.frames.read();
+        let (width, height) = (frame.width, frame.height);
+
+        let colors = self.palettes[self.palette].colors;
+        self.persistence.apply(frame, &colors, &mut self.pixels);
+
+        upload(&mut self.texture, &self.pixels, [width as u32, height as u32]);
+
+        let canvas_width = (args.window_size[0] / TEXT_SCALE) as usize;
+        let canvas_height = (args.window_size[1] / TEXT_SCALE) as usize;
+        // The debugger gives the game part of the window
+        let area = match self.debugger {
+            Some(ref debugger) => {
+                let area = debugger.game_area(canvas_width, canvas_height);
+                [area[0] as f64 * TEXT_SCALE, area[1] as f64 * TEXT_SCALE, area[2] as f64 * TEXT_SCALE, area[3] as f64 * TEXT_SCALE]
+            },
+            None => [0.0, 0.0, args.window_size[0], args.window_size[1]],
+        };
+
+        // Scale by the largest factor that fits and letterbox the rest
+        let scale = (area[2] / width as f64).min(area[3] / height as f64);
+        let draw_width = width as f64 * scale;
+        let draw_height = height as f64 * scale;
+        let x = area[0] + (area[2] - draw_width) / 2.0;
+        let y = area[1] + (area[3] - draw_height) / 2.0;
+
+        let background = [
+            colors[0][0] as f32 / 255.0,
+            colors[0][1] as f32 / 255.0,
+            colors[0][2] as f32 / 255.0,
+            1.0,
+        ];
+        self.overlay.tick(&self.stats);
+        self.canvas.reset(canvas_width, canvas_height, colors[0]);
+        let mut show_canvas = if let Some(ref browser) = self.browser {
+            browser.draw(&mut self.canvas, &colors);
+            true
+        } else if let Some(ref mut debugger) = self.debugger {
+            debugger.draw(&mut self.canvas, &self.stats, &colors);
+            true
+        } else {
+            self.overlay.draw(&mut self.canvas, &self.stats, &colors)
+        };
+        if let (Some(ref panel), Some(ref snapshot), None) = (&self.cheat_panel, &self.debug_snapshot, &self.browser) {
+            // With the debugger open the canvas is under the game, so keep below it
+            let top = match self.debugger {
+                Some(ref debugger) => debugger.game_area(canvas_width, canvas_height)[3],
+                None => 0,
+            };
+            let snapshot = snapshot.lock().unwrap();
+            panel.draw(&mut self.canvas, canvas_width, top, &snapshot.ram, &self.cheats, &colors);
+            show_canvas = true;
+        }
+        // A minimized window has nothing to draw text into
+        let show_canvas = show_canvas && canvas_width > 0 && canvas_height > 0;
+        if show_canvas {
+            upload(&mut self.canvas_texture, &self.canvas.pixels, [canvas_width as u32, canvas_height as u32]);
         }
+
+        let texture = self.texture.as_ref().unwrap();
+        let canvas_texture = self.canvas_texture.as_ref().filter(|_| show_canvas);
+        let browsing = self.browser.is_some();
+        // The debugger panels go under the game, and the overlay over it
+        let canvas_under = self.debugger.is_some() && !browsing;
+        let window_size = args.window_size;
+        self.gl.draw(args.viewport(), |c, gl| {
+            clear(background, gl);
+            let draw_canvas = |gl: &mut GlGraphics| {
+                if let Some(canvas_texture) = canvas_texture {
+                    Image::new()
+                        .rect([0.0, 0.0, window_size[0], window_size[1]])
+                        .draw(canvas_texture, &c.draw_state, c.transform, gl);
+                }
+            };
+            if canvas_under {
+                draw_canvas(gl);
+            }
+            // The browser covers the game
+            if !browsing {
+                Image::new()
+                    .rect([x, y, draw_width, draw_height])
+                    .draw(texture, &c.draw_state, c.transform, gl);
+            }
+            if !canvas_under {
+                draw_canvas(gl);
+            }
+        });
+    }
+}
+
+// Copies RGBA `pixels` into the texture, creating it when the size changes
+fn upload(texture: &mut Option<Texture>, pixels: &[u8], size: [u32; 2]) {
+    use graphics::ImageSize;
+
+    match texture {
+        Some(ref mut texture) if texture.get_size() == (size[0], size[1]) => {
+            UpdateTexture::update(texture, &mut (), Format::Rgba8, pixels, [0, 0], size).unwrap();
+        },
+        _ => {
+            let settings = TextureSettings::new().filter(Filter::Nearest);
+            *texture = Some(Texture::create(&mut (), Format::Rgba8, pixels, size, &settings).unwrap());
+        },
     }
 }
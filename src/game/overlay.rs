@@ -0,0 +1,102 @@
+use super::canvas::{Canvas, LABEL_ALPHA};
+use super::font::LINE_HEIGHT;
+use crate::interpreter::Stats;
+use std::sync::atomic::Ordering;
+use std::time;
+
+// How long a notification stays on screen
+const NOTIFICATION_TIME: f64 = 2.0;
+
+// Text drawn over the game: a status panel toggled by a hotkey, short-lived
+// notifications, and the error that stopped the program
+pub struct Overlay {
+    visible: bool,
+    notifications: Vec<(String, time::Instant)>,
+    last_second: time::Instant,
+    frames_drawn: u64,
+    last_instructions: u64,
+    fps: u64,
+    ips: u64,
+}
+
+impl Overlay {
+    pub fn new() -> Self {
+        Overlay {
+            visible: false,
+            notifications: Vec::new(),
+            last_second: time::Instant::now(),
+            frames_drawn: 0,
+            last_instructions: 0,
+            fps: 0,
+            ips: 0,
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+    }
+
+    pub fn notify(&mut self, message: String) {
+        self.notifications.push((message, time::Instant::now()));
+    }
+
+    // Counts a rendered frame and samples the rates once a second
+    pub fn tick(&mut self, stats: &Stats) {
+        self.frames_drawn += 1;
+        if self.last_second.elapsed().as_secs_f64() >= 1.0 {
+            let instructions = stats.instructions.load(Ordering::Relaxed);
+            self.ips = instructions.saturating_sub(self.last_instructions);
+            self.last_instructions = instructions;
+            self.fps = self.frames_drawn;
+            self.frames_drawn = 0;
+            self.last_second = time::Instant::now();
+        }
+        self.notifications.retain(|(_, shown)| shown.elapsed().as_secs_f64() < NOTIFICATION_TIME);
+    }
+
+    // Draws onto a transparent canvas, returning false if there was nothing to show
+    pub fn draw(&self, canvas: &mut Canvas, stats: &Stats, colors: &[[u8; 4]; 4]) -> bool {
+        canvas.reset(canvas.width, canvas.height, [0, 0, 0, 0]);
+        let (foreground, mut background) = (colors[1], colors[0]);
+        background[3] = LABEL_ALPHA;
+        let mut drawn = false;
+
+        if self.visible {
+            let mut status = Vec::new();
+            if stats.halted.load(Ordering::Relaxed) {
+                status.push("HALTED");
+            } else if stats.waiting_for_key.load(Ordering::Relaxed) {
+                status.push("WAITING FOR KEY");
+            }
+            if stats.paused.load(Ordering::Relaxed) {
+                status.push("PAUSED");
+            }
+            if stats.turbo.load(Ordering::Relaxed) {
+                status.push("TURBO");
+            }
+            let lines = [
+                format!("FPS {}", self.fps),
+                format!("{} instr/s", self.ips),
+                format!("PC {:04X}", stats.pc.load(Ordering::Relaxed)),
+                status.join(" "),
+            ];
+            for (i, line) in lines.iter().filter(|line| !line.is_empty()).enumerate() {
+                canvas.draw_label(2, 2 + i * LINE_HEIGHT, line, foreground, background);
+            }
+            drawn = true;
+        }
+
+        // Messages stack up from the bottom, with any error lowest
+        let mut messages: Vec<&str> = self.notifications.iter().map(|(message, _)| message.as_str()).collect();
+        let error = stats.error.lock().unwrap();
+        if let Some(ref error) = *error {
+            messages.push(error);
+        }
+        for (i, message) in messages.iter().rev().enumerate() {
+            let y = canvas.height.saturating_sub((i + 1) * LINE_HEIGHT + 2);
+            canvas.draw_label(2, y, message, foreground, background);
+            drawn = true;
+        }
+        return drawn;
+    }
+}
@@ -0,0 +1,194 @@
+use crate::display::Frame;
+use std::collections::VecDeque;
+
+// Display filters that hide the flicker of sprites being XOR-erased and redrawn.
+// They only change what is shown, never the emulated framebuffer.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Persistence {
+    Off,
+    // Lit pixels show at once, and pixels turned off fade out, keeping this
+    // fraction of their old color each frame
+    Decay(f32),
+    // A pixel stays lit while it was lit in any of this many recent frames
+    Or(usize),
+}
+
+impl Persistence {
+    // Parses "off", "decay[:strength]" or "or[:frames]"
+    pub fn from_name(name: &str) -> Option<Self> {
+        let mut parts = name.splitn(2, ':');
+        let mode = parts.next()?.to_lowercase();
+        let arg = parts.next();
+        match mode.as_str() {
+            "off" | "none" => Some(Persistence::Off),
+            "decay" => {
+                let strength = arg.map_or(Some(0.6), |a| a.parse::<f32>().ok().filter(|s| s.is_finite()))?;
+                Some(Persistence::Decay(strength.clamp(0.0, 0.95)))
+            },
+            "or" => {
+                let frames: usize = arg.map_or(Some(2), |a| a.parse().ok())?;
+                Some(Persistence::Or(frames.max(1)))
+            },
+            _ => None,
+        }
+    }
+}
+
+pub struct PersistenceFilter {
+    mode: Persistence,
+    history: VecDeque<Vec<u8>>,
+    glow: Vec<[f32; 3]>,
+    // The number and colors of the last frame shown
+    number: Option<u64>,
+    colors: [[u8; 4]; 4],
+}
+
+impl PersistenceFilter {
+    pub fn new(mode: Persistence) -> Self {
+        PersistenceFilter {
+            mode,
+            history: VecDeque::new(),
+            glow: Vec::new(),
+            number: None,
+            colors: [[0x00; 4]; 4],
+        }
+    }
+
+    // Writes the filtered RGBA image of `frame` into `out`. Fading and
+    // history move on with the emulated frames that passed since the last
+    // call, not with how often the window is drawn.
+    pub fn apply(&mut self, frame: &Frame, colors: &[[u8; 4]; 4], out: &mut Vec<u8>) {
+        out.clear();
+        let frames = match self.number {
+            Some(number) if frame.number >= number => frame.number - number,
+            _ => 1,
+        };
+        // Anything remembered from another resolution, palette or program is meaningless
+        if self.glow.len() != frame.pixels.len() || self.colors != *colors || self.number.is_some_and(|n| frame.number < n) {
+            self.history.clear();
+            self.glow = frame.pixels.iter().map(|pixel| rgb(&colors[*pixel as usize & 0x03])).collect();
+        }
+        self.number = Some(frame.number);
+        self.colors = *colors;
+        match self.mode {
+            Persistence::Off => {
+                for pixel in frame.pixels.iter() {
+                    out.extend_from_slice(&colors[*pixel as usize & 0x03]);
+                }
+            },
+            Persistence::Decay(strength) => {
+                let fade = strength.powi(frames.min(i32::MAX as u64) as i32);
+                for (glow, pixel) in self.glow.iter_mut().zip(frame.pixels.iter()) {
+                    let target = rgb(&colors[*pixel as usize & 0x03]);
+                    // Going by the index rather than brightness keeps palettes
+                    // with a light background from fading pixels in
+                    if *pixel & 0x03 != 0 {
+                        *glow = target;
+                    } else {
+                        for channel in 0..3 {
+                            glow[channel] = target[channel] + (glow[channel] - target[channel]) * fade;
+                        }
+                    }
+                    out.extend_from_slice(&[glow[0] as u8, glow[1] as u8, glow[2] as u8, 0xFF]);
+                }
+            },
+            Persistence::Or(count) => {
+                // A frame published again within the same emulated frame
+                // replaces it, and frames nothing was published for looked
+                // like the one before them
+                if frames == 0 {
+                    self.history.pop_front();
+                }
+                for _ in 1..frames.min(count as u64) {
+                    if let Some(last) = self.history.front().cloned() {
+                        self.history.push_front(last);
+                    }
+                }
+                self.history.push_front(frame.pixels.clone());
+                self.history.truncate(count);
+                for idx in 0..frame.pixels.len() {
+                    let pixel = self.history.iter().fold(0, |acc, past| acc | past[idx]);
+                    out.extend_from_slice(&colors[pixel as usize & 0x03]);
+                }
+            },
+        }
+    }
+}
+
+fn rgb(color: &[u8; 4]) -> [f32; 3] {
+    [color[0] as f32, color[1] as f32, color[2] as f32]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COLORS: [[u8; 4]; 4] = [
+        [0x00, 0x00, 0x00, 0xFF],
+        [0xFF, 0xFF, 0xFF, 0xFF],
+        [0xFF, 0x00, 0x00, 0xFF],
+        [0x00, 0xFF, 0x00, 0xFF],
+    ];
+
+    // Shows `pixels` as emulated frame `number`
+    fn shown(filter: &mut PersistenceFilter, number: u64, pixels: &[u8], colors: &[[u8; 4]; 4]) -> Vec<u8> {
+        let frame = Frame { width: pixels.len(), height: 1, pixels: pixels.to_vec(), number };
+        let mut out = Vec::new();
+        filter.apply(&frame, colors, &mut out);
+        return out.chunks(4).map(|rgba| rgba[0]).collect();
+    }
+
+    #[test]
+    fn decay_lights_pixels_at_once_and_fades_them_out() {
+        let mut filter = PersistenceFilter::new(Persistence::Decay(0.5));
+        assert_eq!(shown(&mut filter, 0, &[0, 0], &COLORS), vec![0x00, 0x00]);
+        assert_eq!(shown(&mut filter, 1, &[1, 0], &COLORS), vec![0xFF, 0x00]);
+        assert_eq!(shown(&mut filter, 2, &[0, 1], &COLORS), vec![0x7F, 0xFF]);
+        assert_eq!(shown(&mut filter, 3, &[0, 0], &COLORS), vec![0x3F, 0x7F]);
+    }
+
+    #[test]
+    fn decay_goes_by_index_with_a_light_background() {
+        let inverted = [COLORS[1], COLORS[0], COLORS[2], COLORS[3]];
+        let mut filter = PersistenceFilter::new(Persistence::Decay(0.5));
+        assert_eq!(shown(&mut filter, 0, &[0], &inverted), vec![0xFF]);
+        // The dark lit pixel appears at once and fades back to the background
+        assert_eq!(shown(&mut filter, 1, &[1], &inverted), vec![0x00]);
+        assert_eq!(shown(&mut filter, 2, &[0], &inverted), vec![0x7F]);
+    }
+
+    #[test]
+    fn decay_fades_with_emulated_frames_not_redraws() {
+        let mut filter = PersistenceFilter::new(Persistence::Decay(0.5));
+        assert_eq!(shown(&mut filter, 0, &[1], &COLORS), vec![0xFF]);
+        // Drawing the same frame again, as a fast monitor does, changes nothing
+        assert_eq!(shown(&mut filter, 1, &[0], &COLORS), vec![0x7F]);
+        assert_eq!(shown(&mut filter, 1, &[0], &COLORS), vec![0x7F]);
+        assert_eq!(shown(&mut filter, 1, &[0], &COLORS), vec![0x7F]);
+        // Two frames passing between redraws fade twice
+        assert_eq!(shown(&mut filter, 3, &[0], &COLORS), vec![0x1F]);
+    }
+
+    #[test]
+    fn or_keeps_pixels_lit_for_the_given_frames() {
+        let mut filter = PersistenceFilter::new(Persistence::Or(2));
+        assert_eq!(shown(&mut filter, 0, &[1, 0], &COLORS), vec![0xFF, 0x00]);
+        assert_eq!(shown(&mut filter, 1, &[0, 0], &COLORS), vec![0xFF, 0x00]);
+        assert_eq!(shown(&mut filter, 2, &[0, 0], &COLORS), vec![0x00, 0x00]);
+    }
+
+    #[test]
+    fn or_counts_emulated_frames_not_redraws() {
+        let mut filter = PersistenceFilter::new(Persistence::Or(2));
+        assert_eq!(shown(&mut filter, 0, &[1, 0], &COLORS), vec![0xFF, 0x00]);
+        // The sprite moves within frame 1, so only the newer picture counts
+        assert_eq!(shown(&mut filter, 1, &[0, 0], &COLORS), vec![0xFF, 0x00]);
+        assert_eq!(shown(&mut filter, 1, &[0, 1], &COLORS), vec![0xFF, 0xFF]);
+        assert_eq!(shown(&mut filter, 1, &[0, 1], &COLORS), vec![0xFF, 0xFF]);
+        assert_eq!(shown(&mut filter, 2, &[0, 0], &COLORS), vec![0x00, 0xFF]);
+        // Frames with nothing published held the last picture
+        assert_eq!(shown(&mut filter, 3, &[1, 0], &COLORS), vec![0xFF, 0x00]);
+        assert_eq!(shown(&mut filter, 5, &[0, 0], &COLORS), vec![0xFF, 0x00]);
+        assert_eq!(shown(&mut filter, 7, &[0, 0], &COLORS), vec![0x00, 0x00]);
+    }
+}
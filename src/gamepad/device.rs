@@ -0,0 +1,145 @@
+use crate::gamepad::{GamepadBackend, GamepadEvent};
+use gilrs::{Axis, Button, EventType, Gilrs};
+use std::collections::HashMap;
+use std::io;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+// Full deflection in the Linux joystick API's units, which mappings are written in
+const AXIS_MAX: f32 = 32767.0;
+// The D-pad is reported on these axes, as the joystick API does for most pads
+const DPAD_X: u8 = 6;
+const DPAD_Y: u8 = 7;
+
+// Controllers read through gilrs, numbered the way the Linux joystick API
+// numbers an Xbox-style pad so the same mappings work everywhere
+pub struct GilrsGamepad {
+    events: Receiver<GamepadEvent>,
+}
+
+impl GilrsGamepad {
+    // Reads every connected controller whose name contains `name`, or all of
+    // them for "any"
+    pub fn open(name: &str) -> io::Result<Self> {
+        let name = name.to_lowercase();
+        let (sender, receiver) = mpsc::channel();
+        let (ready_sender, ready) = mpsc::channel();
+        // Gilrs has to stay on the thread that made it
+        thread::spawn(move || {
+            let mut gilrs = match Gilrs::new() {
+                Ok(gilrs) => gilrs,
+                Err(e) => {
+                    let _ = ready_sender.send(Err(io::Error::other(e.to_string())));
+                    return;
+                },
+            };
+            let _ = ready_sender.send(Ok(()));
+            let mut dpads: HashMap<gilrs::GamepadId, Dpad> = HashMap::new();
+            while let Some(event) = gilrs.next_event_blocking(None) {
+                let pad_name = gilrs.gamepad(event.id).name().to_lowercase();
+                if name != "any" && !pad_name.contains(&name) {
+                    continue;
+                }
+                if let Some(event) = translate(event.event, dpads.entry(event.id).or_default()) {
+                    if sender.send(event).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+        ready.recv().map_err(|_| io::Error::other("gamepad thread died"))??;
+        return Ok(GilrsGamepad { events: receiver });
+    }
+}
+
+impl GamepadBackend for GilrsGamepad {
+    fn next_event(&mut self) -> Option<GamepadEvent> {
+        self.events.recv().ok()
+    }
+}
+
+// Which D-pad buttons are held, so letting go of one direction while the
+// opposite one is still down leaves the axis pointing that way
+#[derive(Default)]
+struct Dpad {
+    // Left, right, up and down
+    held: [bool; 4],
+}
+
+impl Dpad {
+    fn button(&mut self, button: Button, pressed: bool) -> Option<GamepadEvent> {
+        let (idx, axis) = match button {
+            Button::DPadLeft => (0, DPAD_X),
+            Button::DPadRight => (1, DPAD_X),
+            Button::DPadUp => (2, DPAD_Y),
+            Button::DPadDown => (3, DPAD_Y),
+            _ => return None,
+        };
+        self.held[idx] = pressed;
+        let pair = idx & !1;
+        let direction = self.held[pair + 1] as i16 - self.held[pair] as i16;
+        return Some(GamepadEvent::Axis(axis, direction * AXIS_MAX as i16));
+    }
+}
+
+fn translate(event: EventType, dpad: &mut Dpad) -> Option<GamepadEvent> {
+    match event {
+        EventType::ButtonPressed(button, _) => dpad.button(button, true).or_else(|| button_event(button, true)),
+        EventType::ButtonReleased(button, _) => dpad.button(button, false).or_else(|| button_event(button, false)),
+        EventType::AxisChanged(axis, value, _) => {
+            let (axis, value) = match axis {
+                Axis::LeftStickX => (0, value),
+                // Gilrs has up as positive, the joystick API down
+                Axis::LeftStickY => (1, -value),
+                Axis::LeftZ => (2, value),
+                Axis::RightStickX => (3, value),
+                Axis::RightStickY => (4, -value),
+                Axis::RightZ => (5, value),
+                Axis::DPadX => (DPAD_X, value),
+                Axis::DPadY => (DPAD_Y, -value),
+                _ => return None,
+            };
+            Some(GamepadEvent::Axis(axis, (value.clamp(-1.0, 1.0) * AXIS_MAX) as i16))
+        },
+        _ => None,
+    }
+}
+
+fn button_event(button: Button, pressed: bool) -> Option<GamepadEvent> {
+    let number = match button {
+        Button::South => 0,
+        Button::East => 1,
+        Button::West => 2,
+        Button::North => 3,
+        Button::LeftTrigger => 4,
+        Button::RightTrigger => 5,
+        Button::Select => 6,
+        Button::Start => 7,
+        Button::Mode => 8,
+        Button::LeftThumb => 9,
+        Button::RightThumb => 10,
+        _ => return None,
+    };
+    return Some(GamepadEvent::Button(number, pressed));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FULL: i16 = AXIS_MAX as i16;
+
+    #[test]
+    fn dpad_releases_leave_the_opposite_direction_held() {
+        let mut dpad = Dpad::default();
+        assert_eq!(dpad.button(Button::DPadLeft, true), Some(GamepadEvent::Axis(DPAD_X, -FULL)));
+        assert_eq!(dpad.button(Button::DPadRight, true), Some(GamepadEvent::Axis(DPAD_X, 0)));
+        assert_eq!(dpad.button(Button::DPadLeft, false), Some(GamepadEvent::Axis(DPAD_X, FULL)));
+        // The other axis is tracked on its own
+        assert_eq!(dpad.button(Button::DPadUp, true), Some(GamepadEvent::Axis(DPAD_Y, -FULL)));
+        assert_eq!(dpad.button(Button::DPadRight, false), Some(GamepadEvent::Axis(DPAD_X, 0)));
+        assert_eq!(dpad.button(Button::DPadDown, true), Some(GamepadEvent::Axis(DPAD_Y, 0)));
+        assert_eq!(dpad.button(Button::DPadUp, false), Some(GamepadEvent::Axis(DPAD_Y, FULL)));
+        assert_eq!(dpad.button(Button::South, true), None);
+    }
+}
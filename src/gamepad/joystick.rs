@@ -0,0 +1,39 @@
+use crate::gamepad::{GamepadBackend, GamepadEvent};
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+
+const JS_EVENT_BUTTON: u8 = 0x01;
+const JS_EVENT_AXIS: u8 = 0x02;
+// Set on the synthetic events describing the initial state
+const JS_EVENT_INIT: u8 = 0x80;
+
+// A controller read through the Linux joystick API, e.g. /dev/input/js0
+pub struct Joystick {
+    device: File,
+}
+
+impl Joystick {
+    pub fn open(path: &str) -> io::Result<Self> {
+        return Ok(Joystick {
+            device: File::open(path)?,
+        });
+    }
+}
+
+impl GamepadBackend for Joystick {
+    fn next_event(&mut self) -> Option<GamepadEvent> {
+        // struct js_event { u32 time; i16 value; u8 type; u8 number; }
+        let mut event = [0u8; 8];
+        loop {
+            self.device.read_exact(&mut event).ok()?;
+            let value = i16::from_le_bytes([event[4], event[5]]);
+            let number = event[7];
+            match event[6] & !JS_EVENT_INIT {
+                JS_EVENT_BUTTON => return Some(GamepadEvent::Button(number, value != 0)),
+                JS_EVENT_AXIS => return Some(GamepadEvent::Axis(number, value)),
+                _ => continue,
+            }
+        }
+    }
+}
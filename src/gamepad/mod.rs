@@ -0,0 +1,332 @@
+#[cfg(any(feature = "gilrs", not(target_os = "linux")))]
+mod device;
+#[cfg(all(not(feature = "gilrs"), target_os = "linux"))]
+mod joystick;
+// Stands in for a real device in the tests
+#[cfg(test)]
+mod virtual_pad;
+
+#[cfg(any(feature = "gilrs", not(target_os = "linux")))]
+pub use device::GilrsGamepad;
+#[cfg(all(not(feature = "gilrs"), target_os = "linux"))]
+pub use joystick::Joystick;
+
+use crate::config::{Config, Value};
+use crate::game::{GameState, KeySource, KeyState};
+use std::collections::HashMap;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+// Axis positions run from -32767 to 32767 and count as pushed past this
+const DEFAULT_DEADZONE: i32 = 16384;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum GamepadEvent {
+    Button(u8, bool), // Button, pressed
+    Axis(u8, i16), // Axis, position
+}
+
+// Something that produces controller events, a real device or a stand-in
+pub trait GamepadBackend: Send {
+    // Blocks until the next event, returning None once the device is gone
+    fn next_event(&mut self) -> Option<GamepadEvent>;
+}
+
+// Which keypad keys each button and axis direction presses. Read from
+// [gamepad.buttons] and [gamepad.axes], then [roms.<rom name>.gamepad.*]:
+//
+//     [gamepad.buttons]
+//     0 = "5"
+//     [gamepad.axes]
+//     "1-" = "1"
+//     "1+" = "4"
+#[derive(Clone, PartialEq, Debug)]
+pub struct GamepadMapping {
+    buttons: HashMap<u8, Vec<u8>>,
+    // Keyed by axis and direction, true for positive
+    axes: HashMap<(u8, bool), Vec<u8>>,
+    deadzone: i32,
+}
+
+impl GamepadMapping {
+    // The left stick and D-pad move with 5/8/7/9 and the face buttons press 6 and 4
+    pub fn new() -> Self {
+        let mut mapping = GamepadMapping {
+            buttons: HashMap::new(),
+            axes: HashMap::new(),
+            deadzone: DEFAULT_DEADZONE,
+        };
+        mapping.buttons.insert(0, vec![0x6]);
+        mapping.buttons.insert(1, vec![0x4]);
+        // Axes 0/1 are usually the left stick and 6/7 the D-pad
+        for (x_axis, y_axis) in [(0, 1), (6, 7)].iter() {
+            mapping.axes.insert((*x_axis, false), vec![0x7]);
+            mapping.axes.insert((*x_axis, true), vec![0x9]);
+            mapping.axes.insert((*y_axis, false), vec![0x5]);
+            mapping.axes.insert((*y_axis, true), vec![0x8]);
+        }
+        return mapping;
+    }
+
+    pub fn load(config: &Config, rom_name: &str) -> Result<Self, String> {
+        let mut mapping = GamepadMapping::new();
+        if let Some(table) = config.get("gamepad") {
+            mapping.apply(table)?;
+        }
+        let rom_table = config.get("roms")
+            .and_then(|roms| roms.as_table())
+            .and_then(|roms| roms.get(rom_name))
+            .and_then(|rom| rom.get("gamepad"));
+        if let Some(table) = rom_table {
+            mapping.apply(table)?;
+        }
+        return Ok(mapping);
+    }
+
+    pub fn apply(&mut self, table: &Value) -> Result<(), String> {
+        if let Some(deadzone) = table.get("deadzone") {
+            self.deadzone = deadzone.as_integer().ok_or("gamepad deadzone must be a number")? as i32;
+        }
+        if let Some(buttons) = table.get("buttons").and_then(|v| v.as_table()) {
+            for (button, keys) in buttons.iter() {
+                let button = button.parse().map_err(|_| format!("invalid gamepad button {}", button))?;
+                self.buttons.insert(button, parse_keys(keys)?);
+            }
+        }
+        if let Some(axes) = table.get("axes").and_then(|v| v.as_table()) {
+            for (axis, keys) in axes.iter() {
+                let positive = axis.ends_with('+');
+                if !positive && !axis.ends_with('-') {
+                    return Err(format!("gamepad axis {} needs a + or - direction", axis));
+                }
+                let axis_id = axis[..axis.len() - 1].parse().map_err(|_| format!("invalid gamepad axis {}", axis))?;
+                self.axes.insert((axis_id, positive), parse_keys(keys)?);
+            }
+        }
+        return Ok(());
+    }
+}
+
+fn parse_keys(value: &Value) -> Result<Vec<u8>, String> {
+    let names = match value {
+        Value::Array(names) => names.iter().collect(),
+        _ => vec![value],
+    };
+    names.iter().map(|name| {
+        name.as_str()
+            .and_then(|name| u8::from_str_radix(name, 16).ok())
+            .filter(|key_id| *key_id <= 0xF)
+            .ok_or_else(|| format!("gamepad binding {:?} is not a keypad key", name))
+    }).collect()
+}
+
+// The controller named by --gamepad or gamepad.device: a name to look for,
+// or "any", through gilrs when built with it, and a joystick device such as
+// /dev/input/js0 otherwise
+#[cfg(any(feature = "gilrs", not(target_os = "linux")))]
+pub fn open(device: &str) -> io::Result<Box<dyn GamepadBackend>> {
+    return Ok(Box::new(GilrsGamepad::open(device)?));
+}
+
+#[cfg(all(not(feature = "gilrs"), target_os = "linux"))]
+pub fn open(device: &str) -> io::Result<Box<dyn GamepadBackend>> {
+    return Ok(Box::new(Joystick::open(device)?));
+}
+
+// Tracks the controller and turns it into keypad presses
+pub struct GamepadInput {
+    mapping: GamepadMapping,
+    buttons: HashMap<u8, bool>,
+    axes: HashMap<u8, i16>,
+}
+
+impl GamepadInput {
+    pub fn new(mapping: GamepadMapping) -> Self {
+        GamepadInput {
+            mapping,
+            buttons: HashMap::new(),
+            axes: HashMap::new(),
+        }
+    }
+
+    pub fn handle(&mut self, event: GamepadEvent) {
+        match event {
+            GamepadEvent::Button(button, pressed) => { self.buttons.insert(button, pressed); },
+            GamepadEvent::Axis(axis, position) => { self.axes.insert(axis, position); },
+        }
+    }
+
+    // Bitmask of the keypad keys currently held through the controller
+    pub fn keypad_mask(&self) -> u16 {
+        let mut mask = 0;
+        for (button, keys) in self.mapping.buttons.iter() {
+            if self.buttons.get(button) == Some(&true) {
+                mask |= keys_mask(keys);
+            }
+        }
+        for ((axis, positive), keys) in self.mapping.axes.iter() {
+            let position = *self.axes.get(axis).unwrap_or(&0) as i32;
+            let pushed = if *positive {
+                position > self.mapping.deadzone
+            } else {
+                position < -self.mapping.deadzone
+            };
+            if pushed {
+                mask |= keys_mask(keys);
+            }
+        }
+        return mask;
+    }
+
+    // Feeds the keypad from `backend` on its own thread until the device goes
+    // away, returning the input for the mapping to be swapped while it runs
+    pub fn start(self, backend: Box<dyn GamepadBackend>, state: Arc<Mutex<GameState>>) -> Arc<Mutex<GamepadInput>> {
+        let input = Arc::new(Mutex::new(self));
+        let shared = input.clone();
+        thread::spawn(move || GamepadInput::run(&shared, backend, &state));
+        return input;
+    }
+
+    fn run(input: &Mutex<GamepadInput>, mut backend: Box<dyn GamepadBackend>, state: &Mutex<GameState>) {
+        while let Some(event) = backend.next_event() {
+            let mut input = input.lock().unwrap();
+            input.handle(event);
+            input.update_keypad(&mut state.lock().unwrap());
+        }
+    }
+
+    // Switches to another ROM's mapping, updating the keypad for the controls held now
+    pub fn set_mapping(&mut self, mapping: GamepadMapping, state: &mut GameState) {
+        self.mapping = mapping;
+        self.update_keypad(state);
+    }
+
+    fn update_keypad(&self, state: &mut GameState) {
+        let mask = self.keypad_mask();
+        for key_id in 0..16 {
+            let key_state = if mask & (1 << key_id) != 0 { KeyState::Pressed } else { KeyState::Released };
+            state.set_key_state(KeySource::Gamepad, key_id, key_state);
+        }
+    }
+}
+
+fn keys_mask(keys: &[u8]) -> u16 {
+    keys.iter().fold(0, |acc, key_id| acc | (1 << key_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use virtual_pad::{VirtualGamepad, VirtualGamepadHandle};
+
+    // Plays everything sent to the handle through `input`, returning the keypad afterwards
+    fn play_into(input: &Mutex<GamepadInput>, state: GameState, events: impl FnOnce(&VirtualGamepadHandle)) -> GameState {
+        let (pad, handle) = VirtualGamepad::new();
+        events(&handle);
+        drop(handle);
+        let state = Mutex::new(state);
+        GamepadInput::run(input, Box::new(pad), &state);
+        return state.into_inner().unwrap();
+    }
+
+    fn play(mapping: GamepadMapping, state: GameState, events: impl FnOnce(&VirtualGamepadHandle)) -> GameState {
+        return play_into(&Mutex::new(GamepadInput::new(mapping)), state, events);
+    }
+
+    fn pressed(state: &GameState) -> Vec<u8> {
+        return (0..16).filter(|key_id| state.get_key_state(*key_id) == KeyState::Pressed).collect();
+    }
+
+    #[test]
+    fn buttons_press_their_keys() {
+        let state = play(GamepadMapping::new(), GameState::new(), |pad| pad.press(0));
+        assert_eq!(pressed(&state), vec![0x6]);
+        let state = play(GamepadMapping::new(), GameState::new(), |pad| {
+            pad.press(0);
+            pad.press(1);
+            pad.release(0);
+        });
+        assert_eq!(pressed(&state), vec![0x4]);
+    }
+
+    #[test]
+    fn axes_press_the_key_for_their_direction() {
+        let state = play(GamepadMapping::new(), GameState::new(), |pad| pad.move_axis(0, -32767));
+        assert_eq!(pressed(&state), vec![0x7]);
+        let state = play(GamepadMapping::new(), GameState::new(), |pad| pad.move_axis(0, 32767));
+        assert_eq!(pressed(&state), vec![0x9]);
+        let state = play(GamepadMapping::new(), GameState::new(), |pad| {
+            pad.move_axis(7, -32767);
+            pad.move_axis(1, 32767);
+        });
+        assert_eq!(pressed(&state), vec![0x5, 0x8]);
+        let state = play(GamepadMapping::new(), GameState::new(), |pad| {
+            pad.move_axis(1, 32767);
+            pad.move_axis(1, 0);
+        });
+        assert!(pressed(&state).is_empty());
+    }
+
+    #[test]
+    fn axes_inside_the_deadzone_press_nothing() {
+        let state = play(GamepadMapping::new(), GameState::new(), |pad| pad.move_axis(0, DEFAULT_DEADZONE as i16));
+        assert!(pressed(&state).is_empty());
+        let state = play(GamepadMapping::new(), GameState::new(), |pad| pad.move_axis(0, DEFAULT_DEADZONE as i16 + 1));
+        assert_eq!(pressed(&state), vec![0x9]);
+
+        let config = Config::parse("[gamepad]\ndeadzone = 1000").unwrap();
+        let mapping = GamepadMapping::load(&config, "").unwrap();
+        let state = play(mapping, GameState::new(), |pad| pad.move_axis(0, -1001));
+        assert_eq!(pressed(&state), vec![0x7]);
+    }
+
+    #[test]
+    fn rom_mappings_override_the_global_one() {
+        let config = Config::parse(r#"
+            [gamepad.buttons]
+            0 = "a"
+            [roms.PONG.gamepad.buttons]
+            0 = ["1", "c"]
+            [roms.PONG.gamepad.axes]
+            "1-" = "2"
+        "#).unwrap();
+        let events = |pad: &VirtualGamepadHandle| {
+            pad.press(0);
+            pad.move_axis(1, -32767);
+        };
+        let other = play(GamepadMapping::load(&config, "INVADERS").unwrap(), GameState::new(), events);
+        assert_eq!(pressed(&other), vec![0x5, 0xA]);
+        let pong = play(GamepadMapping::load(&config, "PONG").unwrap(), GameState::new(), events);
+        assert_eq!(pressed(&pong), vec![0x1, 0x2, 0xC]);
+    }
+
+    #[test]
+    fn gamepad_and_keyboard_hold_keys_separately() {
+        let mut state = GameState::new();
+        state.set_key_state(KeySource::Keyboard, 0x6, KeyState::Pressed);
+        let mut state = play(GamepadMapping::new(), state, |pad| {
+            pad.press(0);
+            pad.press(1);
+            pad.release(0);
+        });
+        // Letting go of the button leaves the key down on the keyboard
+        assert_eq!(pressed(&state), vec![0x4, 0x6]);
+        state.set_key_state(KeySource::Keyboard, 0x6, KeyState::Released);
+        state.set_key_state(KeySource::Keyboard, 0x4, KeyState::Released);
+        assert_eq!(pressed(&state), vec![0x4]);
+    }
+
+    #[test]
+    fn swapping_the_mapping_moves_held_controls_to_the_new_keys() {
+        let config = Config::parse(r#"
+            [roms.PONG.gamepad.buttons]
+            0 = "1"
+        "#).unwrap();
+        let input = Mutex::new(GamepadInput::new(GamepadMapping::load(&config, "INVADERS").unwrap()));
+        let mut state = play_into(&input, GameState::new(), |pad| pad.press(0));
+        assert_eq!(pressed(&state), vec![0x6]);
+        input.lock().unwrap().set_mapping(GamepadMapping::load(&config, "PONG").unwrap(), &mut state);
+        assert_eq!(pressed(&state), vec![0x1]);
+    }
+}
@@ -0,0 +1,41 @@
+use crate::gamepad::{GamepadBackend, GamepadEvent};
+use std::sync::mpsc::{self, Receiver, Sender};
+
+// A controller driven from code, for testing mappings without hardware
+pub struct VirtualGamepad {
+    events: Receiver<GamepadEvent>,
+}
+
+#[derive(Clone)]
+pub struct VirtualGamepadHandle {
+    events: Sender<GamepadEvent>,
+}
+
+impl VirtualGamepad {
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new() -> (VirtualGamepad, VirtualGamepadHandle) {
+        let (sender, receiver) = mpsc::channel();
+        return (VirtualGamepad { events: receiver }, VirtualGamepadHandle { events: sender });
+    }
+}
+
+impl GamepadBackend for VirtualGamepad {
+    // Ends once every handle has been dropped
+    fn next_event(&mut self) -> Option<GamepadEvent> {
+        self.events.recv().ok()
+    }
+}
+
+impl VirtualGamepadHandle {
+    pub fn press(&self, button: u8) {
+        let _ = self.events.send(GamepadEvent::Button(button, true));
+    }
+
+    pub fn release(&self, button: u8) {
+        let _ = self.events.send(GamepadEvent::Button(button, false));
+    }
+
+    pub fn move_axis(&self, axis: u8, position: i16) {
+        let _ = self.events.send(GamepadEvent::Axis(axis, position));
+    }
+}
@@ -0,0 +1,201 @@
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+// Per-address access flags
+pub const EXECUTED: u8 = 0x01;
+pub const READ: u8 = 0x02;
+pub const WRITTEN: u8 = 0x04;
+
+// Bytes per row of the heatmap and how many pixels each byte gets across and down
+const HEATMAP_COLUMNS: usize = 64;
+const HEATMAP_SCALE: usize = 4;
+
+// Bytes that were read or written but never run are data, while untouched
+// bytes could be either and are left to be disassembled as code
+pub fn is_data(coverage: &[u8], addr: usize) -> bool {
+    let flags = coverage.get(addr).cloned().unwrap_or(0);
+    return flags & EXECUTED == 0 && flags & (READ | WRITTEN) != 0;
+}
+
+fn describe(flags: u8) -> String {
+    let mut kinds = Vec::new();
+    if flags & EXECUTED != 0 {
+        kinds.push("code");
+    }
+    if flags & READ != 0 {
+        kinds.push("read");
+    }
+    if flags & WRITTEN != 0 {
+        kinds.push("written");
+    }
+    if kinds.is_empty() {
+        kinds.push("untouched");
+    }
+    return kinds.join(", ");
+}
+
+// Writes runs of bytes with the same flags to `path`, covering the program
+// and anything outside it that was touched, then a heatmap of the same
+// next to it as <stem>.heatmap.png
+pub fn write(path: &Path, coverage: &[u8], program: Range<usize>) -> io::Result<()> {
+    let mut report = File::create(path)?;
+    let in_program = |addr: usize| program.contains(&addr);
+    let count = |flag: u8| program.clone().filter(|addr| coverage[*addr] & flag != 0).count();
+    let untouched = program.clone().filter(|addr| coverage[*addr] == 0).count();
+    writeln!(report, "Program {:04X}-{:04X}, {} bytes", program.start, program.end.saturating_sub(1), program.len())?;
+    writeln!(report, "  {:>5} executed", count(EXECUTED))?;
+    writeln!(report, "  {:>5} read", count(READ))?;
+    writeln!(report, "  {:>5} written", count(WRITTEN))?;
+    writeln!(report, "  {:>5} untouched", untouched)?;
+    writeln!(report)?;
+
+    let mut addr = 0;
+    while addr < coverage.len() {
+        let flags = coverage[addr];
+        if flags == 0 && !in_program(addr) {
+            addr += 1;
+            continue;
+        }
+        let start = addr;
+        while addr < coverage.len() && coverage[addr] == flags && (flags != 0 || in_program(addr)) {
+            addr += 1;
+        }
+        writeln!(report, "{:04X}-{:04X}  {:>5}  {}", start, addr - 1, addr - start, describe(flags))?;
+    }
+
+    let last = coverage.iter().rposition(|flags| *flags != 0).unwrap_or(0).max(program.end.saturating_sub(1));
+    return write_heatmap(&heatmap_path(path), &coverage[..=last], program);
+}
+
+// Named after the report's stem so a report that is itself a .png keeps it
+fn heatmap_path(path: &Path) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    return path.with_file_name(format!("{}.heatmap.png", stem));
+}
+
+// One block per byte, HEATMAP_COLUMNS to a row: green for executed, blue for
+// read and red for written, mixed where a byte was used more than one way.
+// Untouched program bytes are grey.
+fn write_heatmap(path: &Path, coverage: &[u8], program: Range<usize>) -> io::Result<()> {
+    let rows = coverage.len().div_ceil(HEATMAP_COLUMNS);
+    let width = HEATMAP_COLUMNS * HEATMAP_SCALE;
+    let height = rows * HEATMAP_SCALE;
+    let mut image = Vec::with_capacity(height * width * 3);
+    for y in 0..height {
+        for x in 0..width {
+            let addr = (y / HEATMAP_SCALE) * HEATMAP_COLUMNS + x / HEATMAP_SCALE;
+            let flags = coverage.get(addr).cloned().unwrap_or(0);
+            let rgb = if flags != 0 {
+                [
+                    if flags & WRITTEN != 0 { 0xFF } else { 0x00 },
+                    if flags & EXECUTED != 0 { 0xFF } else { 0x00 },
+                    if flags & READ != 0 { 0xFF } else { 0x00 },
+                ]
+            } else if program.contains(&addr) {
+                [0x40, 0x40, 0x40]
+            } else {
+                [0x00, 0x00, 0x00]
+            };
+            image.extend_from_slice(&rgb);
+        }
+    }
+
+    let mut encoder = png::Encoder::new(File::create(path)?, width as u32, height as u32);
+    encoder.set_color(png::ColorType::RGB);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&image)?;
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::interpreter;
+    use super::*;
+    use crate::platform::Platform;
+    use std::fs;
+    use std::process;
+
+    // Draws the sprite at 020C, loads V0-V1 from it, stores V0 after them and
+    // loops, leaving 020A and the last byte untouched
+    const PROGRAM: [u16; 9] = [
+        0xA20C, 0xD015, 0xF165, 0xF055, 0x1208,
+        0x0000,
+        0xF090, 0xF090, 0xF000,
+    ];
+
+    fn covered() -> crate::interpreter::Interpreter {
+        let mut interpreter = interpreter(&PROGRAM, Platform::Chip8);
+        interpreter.enable_coverage();
+        interpreter.run(10);
+        return interpreter;
+    }
+
+    #[test]
+    fn flags_how_each_byte_was_used() {
+        let interpreter = covered();
+        let coverage = interpreter.mem.coverage.as_ref().unwrap();
+        assert!(coverage[0x200..0x20A].iter().all(|flags| *flags == EXECUTED));
+        assert_eq!(&coverage[0x20A..0x20C], &[0, 0]);
+        // The sprite and FX65 read, FX55 wrote over the sprite's third byte
+        assert_eq!(&coverage[0x20C..0x211], &[READ, READ, READ | WRITTEN, READ, READ]);
+        assert_eq!(coverage[0x211], 0);
+        assert!(is_data(coverage, 0x20C));
+        assert!(!is_data(coverage, 0x200));
+        assert!(!is_data(coverage, 0x20A));
+        assert_eq!(coverage.iter().filter(|flags| **flags != 0).count(), 15);
+    }
+
+    #[test]
+    fn writes_runs_of_bytes_and_a_heatmap() {
+        let interpreter = covered();
+        let path = std::env::temp_dir().join(format!("chip8rs-coverage-{}.txt", process::id()));
+        interpreter.write_coverage(&path).unwrap();
+        let report = fs::read_to_string(&path).unwrap();
+        let heatmap = File::open(heatmap_path(&path)).unwrap();
+        let (info, mut reader) = png::Decoder::new(heatmap).read_info().unwrap();
+        let mut image = vec![0; info.buffer_size()];
+        reader.next_frame(&mut image).unwrap();
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(heatmap_path(&path)).unwrap();
+
+        assert_eq!(report, "\
+Program 0200-0211, 18 bytes
+     10 executed
+      5 read
+      1 written
+      3 untouched
+
+0200-0209     10  code
+020A-020B      2  untouched
+020C-020D      2  read
+020E-020E      1  read, written
+020F-0210      2  read
+0211-0211      1  untouched
+");
+
+        // Up to the end of the program, a block of pixels per byte
+        assert_eq!((info.width, info.height), (256, 36));
+        assert_eq!(info.color_type, png::ColorType::RGB);
+        let pixel = |addr: usize| {
+            let (x, y) = (addr % HEATMAP_COLUMNS * HEATMAP_SCALE, addr / HEATMAP_COLUMNS * HEATMAP_SCALE);
+            let offset = (y * info.width as usize + x) * 3;
+            return [image[offset], image[offset + 1], image[offset + 2]];
+        };
+        assert_eq!(pixel(0x000), [0x00, 0x00, 0x00]);
+        assert_eq!(pixel(0x200), [0x00, 0xFF, 0x00]);
+        assert_eq!(pixel(0x20A), [0x40, 0x40, 0x40]);
+        assert_eq!(pixel(0x20C), [0x00, 0x00, 0xFF]);
+        assert_eq!(pixel(0x20E), [0xFF, 0x00, 0xFF]);
+    }
+
+    #[test]
+    fn heatmap_never_replaces_the_report() {
+        assert_eq!(heatmap_path(Path::new("out/cov.txt")), Path::new("out/cov.heatmap.png"));
+        assert_eq!(heatmap_path(Path::new("cov.png")), Path::new("cov.heatmap.png"));
+        assert_eq!(heatmap_path(Path::new("coverage")), Path::new("coverage.heatmap.png"));
+    }
+}
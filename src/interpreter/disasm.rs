@@ -0,0 +1,74 @@
+use super::coverage;
+use super::{decode, Instruction};
+use crate::platform::Platform;
+
+// Formats the instruction at `addr` in Cowgod-style mnemonics, returning the
+// text and how many bytes it takes up. Given coverage from a run, bytes that
+// were only used as data come out as DB instead.
+pub fn disassemble(ram: &[u8], coverage: Option<&[u8]>, platform: Platform, addr: usize) -> (String, usize) {
+    if coverage.is_some_and(|coverage| coverage::is_data(coverage, addr)) {
+        return (format!("DB {:02X}", ram.get(addr).cloned().unwrap_or(0)), 1);
+    }
+    let word = |addr: usize| -> u16 {
+        let first_byte = *ram.get(addr).unwrap_or(&0) as u16;
+        let second_byte = *ram.get(addr + 1).unwrap_or(&0) as u16;
+        (first_byte << 8) | second_byte
+    };
+    let byte_code = word(addr);
+    use Instruction::*;
+    let text = match decode(byte_code, platform) {
+        ClearDisplay => "CLS".to_string(),
+        ReturnFromSubroutine => "RET".to_string(),
+        ScrollDown(rows) => format!("SCD {}", rows),
+        ScrollUp(rows) => format!("SCU {}", rows),
+        ScrollRight => "SCR".to_string(),
+        ScrollLeft => "SCL".to_string(),
+        Exit => "EXIT".to_string(),
+        LowRes => "LOW".to_string(),
+        HighRes => "HIGH".to_string(),
+        JumpToLoc(addr) => format!("JP {:03X}", addr),
+        CallSubroutine(addr) => format!("CALL {:03X}", addr),
+        SkipEq(x, byte) => format!("SE V{:X}, {:02X}", x, byte),
+        SkipNeq(x, byte) => format!("SNE V{:X}, {:02X}", x, byte),
+        SkipRegsEq(x, y) => format!("SE V{:X}, V{:X}", x, y),
+        SaveRegRange(x, y) => format!("SAVE V{:X}-V{:X}", x, y),
+        LoadRegRange(x, y) => format!("LOAD V{:X}-V{:X}", x, y),
+        SetReg(x, byte) => format!("LD V{:X}, {:02X}", x, byte),
+        AddReg(x, byte) => format!("ADD V{:X}, {:02X}", x, byte),
+        SetRegFromReg(x, y) => format!("LD V{:X}, V{:X}", x, y),
+        BitwiseOr(x, y) => format!("OR V{:X}, V{:X}", x, y),
+        BitwiseAnd(x, y) => format!("AND V{:X}, V{:X}", x, y),
+        BitwiseXor(x, y) => format!("XOR V{:X}, V{:X}", x, y),
+        AddRegWithCarry(x, y) => format!("ADD V{:X}, V{:X}", x, y),
+        SubReg(x, y) => format!("SUB V{:X}, V{:X}", x, y),
+        ShiftRight(x, y) => format!("SHR V{:X}, V{:X}", x, y),
+        SubRegBackwards(x, y) => format!("SUBN V{:X}, V{:X}", x, y),
+        ShiftLeft(x, y) => format!("SHL V{:X}, V{:X}", x, y),
+        SkipRegsNeq(x, y) => format!("SNE V{:X}, V{:X}", x, y),
+        SetI(addr) => format!("LD I, {:03X}", addr),
+        LongSetI => return (format!("LD I, {:04X}", word(addr + 2)), 4),
+        JumpToLocRel(addr) => format!("JP V0, {:03X}", addr),
+        Random(x, byte) => format!("RND V{:X}, {:02X}", x, byte),
+        DrawSprite(x, y, rows) => format!("DRW V{:X}, V{:X}, {}", x, y, rows),
+        DrawLargeSprite(x, y) => format!("DRW V{:X}, V{:X}, 0", x, y),
+        SkipIfPressed(x) => format!("SKP V{:X}", x),
+        SkipIfNotPressed(x) => format!("SKNP V{:X}", x),
+        SetRegToDelayTimer(x) => format!("LD V{:X}, DT", x),
+        BlockOnKeypress(x) => format!("LD V{:X}, K", x),
+        SetDelayTimer(x) => format!("LD DT, V{:X}", x),
+        SetSoundTimer(x) => format!("LD ST, V{:X}", x),
+        SelectPlanes(planes) => format!("PLANE {}", planes),
+        LoadAudioPattern => "AUDIO".to_string(),
+        SetPitch(x) => format!("PITCH V{:X}", x),
+        AddI(x) => format!("ADD I, V{:X}", x),
+        LoadSprite(x) => format!("LD F, V{:X}", x),
+        LoadLargeSprite(x) => format!("LD HF, V{:X}", x),
+        ToDecimal(x) => format!("LD B, V{:X}", x),
+        CopyRegsIntoMemory(x) => format!("LD [I], V{:X}", x),
+        CopyRegsFromMemory(x) => format!("LD V{:X}, [I]", x),
+        SaveFlags(x) => format!("LD R, V{:X}", x),
+        LoadFlags(x) => format!("LD V{:X}, R", x),
+        InvalidInstruction(byte_code) => format!("DW {:04X}", byte_code),
+    };
+    return (text, 2);
+}
@@ -1,20 +1,46 @@
+mod coverage;
+mod disasm;
+mod profile;
+mod smc;
+
+pub use disasm::disassemble;
+use profile::Profiler;
+use smc::{SmcEvent, SmcMonitor};
+
+use crate::audio::{Audio, Tone};
+use crate::cheat::Cheat;
+use crate::display::{Display, FrameWriter};
 use crate::game::{GameState, KeyState};
+use crate::platform::{Extension, Platform, Quirks, Settings, PROGRAM_ADDR};
 use std::{thread, time};
+use std::io;
+use std::path::Path;
 use std::sync::{Mutex, Arc};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
 use std::num::Wrapping;
-use std::sync::atomic::AtomicU8;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize};
 use std::sync::atomic::Ordering;
 
 // Instructions associated with their decode scheme
-#[derive(Debug)]
+#[derive(Copy, Clone, Debug)]
+#[allow(dead_code, clippy::enum_variant_names)]
 enum Instruction {
     ClearDisplay,
     ReturnFromSubroutine,
+    ScrollDown(u8), // Rows
+    ScrollUp(u8), // Rows
+    ScrollRight,
+    ScrollLeft,
+    Exit,
+    LowRes,
+    HighRes,
     JumpToLoc(u16), // Addr
     CallSubroutine(u16), // Addr
     SkipEq(u8, u8), // Vx, byte
     SkipNeq(u8, u8), // Vx, byte
     SkipRegsEq(u8, u8), // Vx, Vy
+    SaveRegRange(u8, u8), // Vx, Vy
+    LoadRegRange(u8, u8), // Vx, Vy
     SetReg(u8, u8), // Vx, byte
     AddReg(u8, u8), // Vx, byte
     SetRegFromReg(u8, u8), // Vx, Vy
@@ -28,43 +54,69 @@ enum Instruction {
     ShiftLeft(u8, u8), // Vx, Vy (Vy not needed)
     SkipRegsNeq(u8, u8), // Vx, Vy
     SetI(u16), // Addr
+    LongSetI, // Addr is the following word
     JumpToLocRel(u16), // Offset
     Random(u8, u8), // Vx, kk
     DrawSprite(u8, u8, u8), // Vx, Vy, nibble
+    DrawLargeSprite(u8, u8), // Vx, Vy
     SkipIfPressed(u8), // Vx
     SkipIfNotPressed(u8), // Vx
     SetRegToDelayTimer(u8), // Vx
     BlockOnKeypress(u8), // Vx
     SetDelayTimer(u8), // Vx
     SetSoundTimer(u8), // Vx
+    SelectPlanes(u8), // Plane mask
+    LoadAudioPattern,
+    SetPitch(u8), // Vx
     AddI(u8), // Vx
     LoadSprite(u8), // Vx
+    LoadLargeSprite(u8), // Vx
     ToDecimal(u8), // Vx
     CopyRegsIntoMemory(u8), // Vx
     CopyRegsFromMemory(u8), // Vx
+    SaveFlags(u8), // Vx
+    LoadFlags(u8), // Vx
     InvalidInstruction(u16),
 }
 
-fn decode(byte_code: u16) -> Instruction {
+// Opcodes from extensions the platform doesn't have decode as invalid
+fn decode(byte_code: u16, platform: Platform) -> Instruction {
     let first_nibble = ((byte_code & 0xF000) >> 12) as u8;
     let last_nibble = (byte_code & 0x000F) as u8;
     let addr = byte_code & 0x0FFF;
     let x = ((byte_code & 0x0F00) >> 8) as u8;
     let y = ((byte_code & 0x00F0) >> 4) as u8;
     let kk = (byte_code & 0x00FF) as u8;
+    let hires = platform.supports(Extension::HiresClear);
+    let schip = platform.supports(Extension::SuperChip);
+    let xochip = platform.supports(Extension::XoChip);
 
     use Instruction::*;
     match first_nibble {
         0x00 => match byte_code {
             0x00E0 => return ClearDisplay,
+            // HIRES CHIP-8 clears its 64x64 screen through a machine code routine
+            0x0230 if hires => return ClearDisplay,
             0x00EE => return ReturnFromSubroutine,
+            0x00C0..=0x00CF if schip => return ScrollDown(last_nibble),
+            0x00D0..=0x00DF if xochip => return ScrollUp(last_nibble),
+            0x00FB if schip => return ScrollRight,
+            0x00FC if schip => return ScrollLeft,
+            0x00FD if schip => return Exit,
+            0x00FE if schip => return LowRes,
+            0x00FF if schip => return HighRes,
             _ => return InvalidInstruction(byte_code),
         },
         0x01 => return JumpToLoc(addr),
         0x02 => return CallSubroutine(addr),
         0x03 => return SkipEq(x, kk),
         0x04 => return SkipNeq(x, kk),
-        0x05 => return SkipRegsEq(x, y),
+        0x05 => match last_nibble {
+            0x00 => return SkipRegsEq(x, y),
+            0x02 if xochip => return SaveRegRange(x, y),
+            0x03 if xochip => return LoadRegRange(x, y),
+            _ => return InvalidInstruction(byte_code),
+        },
         0x06 => return SetReg(x, kk),
         0x07 => return AddReg(x, kk),
         0x08 => match last_nibble {
@@ -83,6 +135,8 @@ fn decode(byte_code: u16) -> Instruction {
         0x0A => SetI(addr),
         0x0B => JumpToLocRel(addr),
         0x0C => Random(x, kk),
+        // Without SCHIP, DXY0 is an ordinary draw of no rows
+        0x0D if last_nibble == 0 && schip => DrawLargeSprite(x, y),
         0x0D => DrawSprite(x, y, last_nibble),
         0x0E => match kk {
             0x9E => return SkipIfPressed(x),
@@ -90,349 +144,1011 @@ fn decode(byte_code: u16) -> Instruction {
             _ => return InvalidInstruction(byte_code),
         },
         0x0F => match kk {
+            0x00 if x == 0 && xochip => return LongSetI,
+            0x01 if xochip => return SelectPlanes(x),
+            0x02 if x == 0 && xochip => return LoadAudioPattern,
             0x07 => return SetRegToDelayTimer(x),
             0x0A => return BlockOnKeypress(x),
             0x15 => return SetDelayTimer(x),
             0x18 => return SetSoundTimer(x),
             0x1E => return AddI(x),
             0x29 => return LoadSprite(x),
+            0x30 if schip => return LoadLargeSprite(x),
             0x33 => return ToDecimal(x),
+            0x3A if xochip => return SetPitch(x),
             0x55 => return CopyRegsIntoMemory(x),
             0x65 => return CopyRegsFromMemory(x),
+            0x75 if schip => return SaveFlags(x),
+            0x85 if schip => return LoadFlags(x),
             _ => return InvalidInstruction(byte_code),
         },
         _ => return InvalidInstruction(byte_code),
     }
 }
 
+// Frames are published at most this often
+const FRAME_INTERVAL: f64 = 1.0 / 60.0;
+// Instructions run between checks for commands when running as fast as possible
+const UNTHROTTLED_BATCH: u32 = 1000;
+
+// Counters a frontend can show while the interpreter runs on another thread
+pub struct Stats {
+    pub pc: AtomicUsize,
+    pub instructions: AtomicU64,
+    pub waiting_for_key: AtomicBool,
+    pub halted: AtomicBool,
+    pub paused: AtomicBool,
+    pub turbo: AtomicBool,
+    // Why the program stopped, if it went wrong
+    pub error: Mutex<Option<String>>,
+    // Why the interpreter paused itself, until it carries on
+    pub break_reason: Mutex<Option<String>>,
+    // Set along with `break_reason` for a frontend to bring up its debugger
+    pub break_requested: AtomicBool,
+}
+
+// Requests a frontend sends to the interpreter thread through `Interpreter::controls`
+#[derive(Clone, PartialEq, Debug)]
+pub enum Command {
+    TogglePause,
+    // Pause if needed and execute a single instruction
+    Step,
+    // Run as fast as possible instead of at the original speed
+    ToggleTurbo,
+    // Start the loaded program again from a fresh Memory
+    Reset,
+    // Replace the program and start it from a fresh Memory, dropping any cheats
+    Load(Vec<u8>, Settings),
+    // Cheats to apply every frame from now on, replacing any before
+    SetCheats(Vec<Cheat>),
+    // Write a byte to memory once
+    Poke(u16, u8),
+    // Stop interpreting and return from `interpret`
+    Quit,
+}
+
+// What the CPU is doing after a step
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum CpuState {
+    Running,
+    // Stopped at FX0A until a key is pressed and released
+    WaitingForKey,
+    Halted,
+}
+
+// A copy of the machine state for the debugger, refreshed about once a frame
+// while one is attached
+#[derive(Clone, PartialEq, Debug)]
+pub struct Snapshot {
+    // Which instructions the memory should be disassembled as
+    pub platform: Platform,
+    pub pc: usize,
+    pub i_reg: u16,
+    pub registers: [u8; 16],
+    pub stack: Vec<u16>,
+    pub dt_reg: u8,
+    pub st_reg: u8,
+    pub ram: Vec<u8>,
+    // Access flags per address, when coverage is being tracked
+    pub coverage: Option<Vec<u8>>,
+}
+
+// An FX0A in progress. Keys only count once they are pressed after the wait began.
+#[derive(Copy, Clone, PartialEq, Debug)]
+struct KeyWait {
+    reg_idx: u8,
+    armed: u16,
+}
+
 pub struct Interpreter {
     mem: Memory,
     game: Arc<Mutex<GameState>>,
+    platform: Platform,
+    // Kept for resets
+    program: Vec<u8>,
+    display: Display,
+    frames: FrameWriter,
+    // Set when the display has changed since the last published frame
+    frame_dirty: bool,
+    last_frame: time::Instant,
+    quirks: Quirks,
+    // Run `speed` instructions a frame rather than as fast as possible
+    throttled: bool,
+    speed: u32,
+    // Instructions run in the current 60Hz frame, which ends after `speed` of
+    // them, and when it began if throttled
+    frame_cycles: u32,
+    frame_start: time::Instant,
+    // Frames completed, each of which counts the timers down once
+    frame_count: u64,
     running: bool,
+    paused: bool,
+    key_wait: Option<KeyWait>,
+    stats: Arc<Stats>,
+    commands: Option<Receiver<Command>>,
+    debug: Option<Arc<Mutex<Snapshot>>>,
+    cheats: Vec<Cheat>,
+    // When cheats were last applied and the snapshot last taken
+    last_tick: time::Instant,
+    profiler: Option<Profiler>,
+    // Pause on self-modifying code rather than only logging it
+    smc_break: bool,
+    // Fed a frame of sound at the end of every frame
+    audio: Option<Audio>,
 }
 
 impl Interpreter {
-    pub fn new(program: Vec<u16>, game: Arc<Mutex<GameState>>) -> Self {
-        Self { 
-            mem: Memory::new(program),
-            game: game,
-            running: false
+    // Fails if the program doesn't fit in the platform's memory
+    pub fn new(program: Vec<u8>, game: Arc<Mutex<GameState>>, frames: FrameWriter, settings: Settings) -> Result<Self, String> {
+        return Ok(Self { 
+            mem: Memory::new(&program, settings.platform)?,
+            game,
+            platform: settings.platform,
+            program,
+            display: Display::new(settings.platform),
+            frames,
+            frame_dirty: false,
+            last_frame: time::Instant::now(),
+            quirks: settings.quirks,
+            throttled: true,
+            speed: settings.speed,
+            frame_cycles: 0,
+            frame_start: time::Instant::now(),
+            frame_count: 0,
+            running: true,
+            paused: false,
+            key_wait: None,
+            stats: Arc::new(Stats {
+                pc: AtomicUsize::new(0),
+                instructions: AtomicU64::new(0),
+                waiting_for_key: AtomicBool::new(false),
+                halted: AtomicBool::new(false),
+                paused: AtomicBool::new(false),
+                turbo: AtomicBool::new(false),
+                error: Mutex::new(None),
+                break_reason: Mutex::new(None),
+                break_requested: AtomicBool::new(false),
+            }),
+            commands: None,
+            debug: None,
+            cheats: Vec::new(),
+            last_tick: time::Instant::now(),
+            profiler: None,
+            smc_break: false,
+            audio: None,
+        });
+    }
+
+    pub fn stats(&self) -> Arc<Stats> {
+        return self.stats.clone();
+    }
+
+    // Opens the channel a frontend uses to control the interpreter. Once there
+    // is one, `interpret` waits for commands after the program halts and only
+    // returns on Quit or when every sender is gone.
+    pub fn controls(&mut self) -> Sender<Command> {
+        let (sender, receiver) = mpsc::channel();
+        self.commands = Some(receiver);
+        return sender;
+    }
+
+    // Starts keeping a snapshot of the machine up to date for a debugger
+    pub fn debug_snapshot(&mut self) -> Arc<Mutex<Snapshot>> {
+        let snapshot = Arc::new(Mutex::new(self.snapshot()));
+        self.debug = Some(snapshot.clone());
+        return snapshot;
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            platform: self.platform,
+            pc: self.mem.get_pc(),
+            i_reg: self.mem.get_ireg(),
+            registers: self.mem.registers,
+            stack: self.mem.stack.clone(),
+            dt_reg: self.mem.get_dt_reg(),
+            st_reg: self.mem.st_reg,
+            ram: self.mem.ram.clone(),
+            coverage: self.mem.coverage.clone(),
         }
     }
 
+    fn update_snapshot(&mut self) {
+        if let Some(ref debug) = self.debug {
+            *debug.lock().unwrap() = self.snapshot();
+        }
+    }
+
+    pub fn set_cheats(&mut self, cheats: Vec<Cheat>) {
+        self.cheats = cheats;
+        self.apply_cheats();
+    }
+
+    fn apply_cheats(&mut self) {
+        for cheat in self.cheats.iter() {
+            self.mem.poke(cheat.addr, cheat.value);
+        }
+    }
+
+    // Work done about once a frame rather than after every instruction
+    fn tick(&mut self) {
+        self.apply_cheats();
+        self.update_snapshot();
+        self.last_tick = time::Instant::now();
+    }
+
+    // Starts counting executions for `write_profile`
+    pub fn enable_profiler(&mut self) {
+        self.profiler = Some(Profiler::new(RAM_SIZE));
+    }
+
+    pub fn write_profile(&self, path: &Path) -> io::Result<()> {
+        match self.profiler {
+            Some(ref profiler) => return profiler.write(path, &self.mem.ram, self.mem.coverage.as_deref(), self.platform),
+            None => return Ok(()),
+        }
+    }
+
+    // Starts flagging each address as it is executed, read or written
+    pub fn enable_coverage(&mut self) {
+        self.mem.coverage = Some(vec![0; RAM_SIZE]);
+    }
+
+    pub fn write_coverage(&self, path: &Path) -> io::Result<()> {
+        match self.mem.coverage {
+            Some(ref coverage) => {
+                let start = self.mem.program_addr as usize;
+                return coverage::write(path, coverage, start..start + self.program.len());
+            },
+            None => return Ok(()),
+        }
+    }
+
+    // Decoding every instruction as it is fetched is slower, but is there to
+    // compare against
+    pub fn disable_decode_cache(&mut self) {
+        self.mem.decoded = None;
+    }
+
+    // Starts watching for the program writing over its own instructions,
+    // which is logged and can also pause the program
+    pub fn enable_smc_monitor(&mut self, break_on_write: bool) {
+        self.mem.smc = Some(SmcMonitor::new(RAM_SIZE));
+        self.smc_break = break_on_write;
+    }
+
+    fn report_smc(&mut self, event: SmcEvent) {
+        let (writer, _) = disassemble(&self.mem.ram, self.mem.coverage.as_deref(), self.platform, event.writer as usize);
+        let message = match event.fetched_at {
+            Some(pc) => format!("{:04X} runs an instruction written by {:04X} {}", pc, event.writer, writer),
+            None => format!("{:04X} {} wrote over the instruction byte at {:04X}", event.writer, writer, event.addr),
+        };
+        eprintln!("Self-modifying code: {}", message);
+        if self.smc_break {
+            self.paused = true;
+            self.stats.paused.store(true, Ordering::Relaxed);
+            *self.stats.break_reason.lock().unwrap() = Some(message);
+            self.stats.break_requested.store(true, Ordering::Relaxed);
+        }
+    }
+
+    pub fn set_throttled(&mut self, throttled: bool) {
+        self.throttled = throttled;
+        self.stats.turbo.store(!throttled, Ordering::Relaxed);
+    }
+
+    pub fn set_audio(&mut self, audio: Audio) {
+        self.audio = Some(audio);
+    }
+
+    #[allow(dead_code)]
     pub fn print_program(&mut self) {
         for _ in 0..150 {
             let byte_code = self.mem.fetch_instruction();
-            let instruction = decode(byte_code);
+            let instruction = decode(byte_code, self.platform);
             let address = self.mem.get_pc();
-            use Instruction::*;
             println!("{:?}: {:?}", address, instruction);
             self.mem.inc_pc();
         }
     }
 
     pub fn interpret(&mut self) {
-        self.running = true;
-        while self.running {
-            let byte_code = self.mem.fetch_instruction();
-            let address = self.mem.get_pc();
-            let instruction = decode(byte_code);
-            use Instruction::*;
-            // println!("{:?}: {:?}", address, instruction);
-            match instruction {
-                ClearDisplay => {
-                    self.game.lock().unwrap().clear_display();
-                    self.mem.inc_pc();
-                },
-                ReturnFromSubroutine => {
-                    if let Some(addr) = self.mem.pop_stack() {
-                        self.mem.set_pc(addr);
-                    } else {
-                        self.running = false;
-                        eprintln!("Attempted to pop from an empty stack!");
-                    }
+        loop {
+            if !self.running || self.paused {
+                self.stats.halted.store(!self.running, Ordering::Relaxed);
+                self.update_snapshot();
+                // Nothing changes until a command arrives, and none can without a controller
+                let command = match self.commands {
+                    Some(ref commands) => commands.recv().ok(),
+                    None => None,
+                };
+                match command {
+                    Some(command) => self.run_command(command),
+                    None => break,
+                }
+                continue;
+            }
+            self.poll_commands();
+            if self.paused {
+                continue;
+            }
+            // Commands and the clock are only checked between batches, which
+            // last the rest of the frame when throttled
+            let batch = if self.throttled {
+                self.speed.saturating_sub(self.frame_cycles).max(1)
+            } else {
+                UNTHROTTLED_BATCH
+            };
+            let frame_count = self.frame_count;
+            self.run(batch);
+            match self.cpu_state() {
+                // Nothing runs while waiting, but the timers keep counting down
+                CpuState::WaitingForKey => {
+                    self.end_frame();
+                    self.wait_for_frame();
                 },
-                JumpToLoc(addr) => self.mem.set_pc(addr),
-                CallSubroutine(addr) => {
-                    self.mem.push_stack(self.mem.get_pc() as u16 + 2);
+                _ if self.throttled && self.frame_count != frame_count => self.wait_for_frame(),
+                _ => {},
+            }
+            let ticking = self.debug.is_some() || !self.cheats.is_empty();
+            if ticking && self.last_tick.elapsed().as_secs_f64() >= FRAME_INTERVAL {
+                self.tick();
+            }
+        }
+        self.stats.halted.store(true, Ordering::Relaxed);
+    }
+
+    // Plays the frame's sound and counts the timers down at the end of every
+    // frame of `speed` instructions, so both stop with the program when it
+    // pauses or halts
+    fn end_frame(&mut self) {
+        self.frame_cycles = 0;
+        self.frame_count += 1;
+        if let Some(ref mut audio) = self.audio {
+            audio.frame(self.mem.st_reg, &self.mem.tone);
+        }
+        self.mem.tick_timers();
+        // Every frame is shown even if nothing was drawn, so display filters
+        // see time pass, unless frames are ending faster than they can be shown
+        if self.throttled || self.last_frame.elapsed().as_secs_f64() >= FRAME_INTERVAL {
+            self.publish_frame();
+        }
+    }
+
+    // Sleeps out the rest of the frame that just ended
+    fn wait_for_frame(&mut self) {
+        let next_frame = self.frame_start + time::Duration::from_secs_f64(FRAME_INTERVAL);
+        let now = time::Instant::now();
+        if next_frame > now {
+            thread::sleep(next_frame - now);
+            self.frame_start = next_frame;
+        } else {
+            // Running behind, so don't try to catch up
+            self.frame_start = now;
+        }
+    }
+
+    fn poll_commands(&mut self) {
+        loop {
+            let command = match self.commands {
+                Some(ref commands) => commands.try_recv(),
+                None => return,
+            };
+            match command {
+                Ok(command) => self.run_command(command),
+                Err(TryRecvError::Empty) => return,
+                // The frontend is gone, so finish like the window was closed
+                Err(TryRecvError::Disconnected) => self.run_command(Command::Quit),
+            }
+        }
+    }
+
+    fn run_command(&mut self, command: Command) {
+        match command {
+            Command::TogglePause => {
+                self.paused = !self.paused;
+                self.stats.paused.store(self.paused, Ordering::Relaxed);
+                if !self.paused {
+                    *self.stats.break_reason.lock().unwrap() = None;
+                }
+            },
+            Command::Step => {
+                self.paused = true;
+                self.stats.paused.store(true, Ordering::Relaxed);
+                self.step();
+                // Show the result straight away rather than at the next frame
+                if self.frame_dirty {
+                    self.publish_frame();
+                }
+            },
+            Command::ToggleTurbo => self.set_throttled(!self.throttled),
+            Command::Reset => self.load(self.program.clone()),
+            Command::Load(program, settings) => {
+                // Cheats and the profile belong to the old ROM
+                self.cheats.clear();
+                if self.profiler.is_some() {
+                    self.enable_profiler();
+                }
+                if self.mem.coverage.is_some() {
+                    self.enable_coverage();
+                }
+                self.platform = settings.platform;
+                self.quirks = settings.quirks;
+                self.speed = settings.speed;
+                self.load(program);
+            },
+            Command::SetCheats(cheats) => self.set_cheats(cheats),
+            Command::Poke(addr, value) => {
+                self.mem.poke(addr, value);
+                self.update_snapshot();
+            },
+            Command::Quit => {
+                self.running = false;
+                self.commands = None;
+            },
+        }
+    }
+
+    // Starts `program` from scratch
+    fn load(&mut self, program: Vec<u8>) {
+        let mut mem = match Memory::new(&program, self.platform) {
+            Ok(mem) => mem,
+            Err(e) => {
+                self.fail(e);
+                return;
+            },
+        };
+        mem.coverage = self.mem.coverage.take();
+        if self.mem.decoded.is_none() {
+            mem.decoded = None;
+        }
+        if self.mem.smc.is_some() {
+            mem.smc = Some(SmcMonitor::new(RAM_SIZE));
+        }
+        self.mem = mem;
+        self.program = program;
+        if let Some(ref mut profiler) = self.profiler {
+            profiler.restart();
+        }
+        self.display = Display::new(self.platform);
+        self.running = true;
+        self.key_wait = None;
+        self.frame_cycles = 0;
+        // Drop key presses made before the reset
+        self.game.lock().unwrap().take_key_edges();
+        self.stats.waiting_for_key.store(false, Ordering::Relaxed);
+        self.stats.halted.store(false, Ordering::Relaxed);
+        *self.stats.error.lock().unwrap() = None;
+        *self.stats.break_reason.lock().unwrap() = None;
+        self.apply_cheats();
+        self.publish_frame();
+    }
+
+    pub fn is_running(&self) -> bool {
+        return self.running;
+    }
+
+    pub fn cpu_state(&self) -> CpuState {
+        if !self.running {
+            CpuState::Halted
+        } else if self.key_wait.is_some() {
+            CpuState::WaitingForKey
+        } else {
+            CpuState::Running
+        }
+    }
+
+    // Executes the instruction at the program counter, or checks the keypad
+    // again if we are waiting on FX0A
+    pub fn step(&mut self) -> CpuState {
+        self.run(1);
+        return self.cpu_state();
+    }
+
+    // Executes up to `count` instructions, stopping early if the program
+    // halts, pauses or waits for a key, and returns how many ran. The stats
+    // and display are brought up to date once at the end.
+    pub fn run(&mut self, count: u32) -> u32 {
+        if !self.running {
+            return 0;
+        }
+        if let Some(wait) = self.key_wait {
+            self.poll_key_wait(wait);
+            self.stats.waiting_for_key.store(self.key_wait.is_some(), Ordering::Relaxed);
+            return 0;
+        }
+        let mut executed = 0;
+        while executed < count {
+            self.execute();
+            executed += 1;
+            self.frame_cycles += 1;
+            if self.frame_cycles >= self.speed {
+                self.end_frame();
+            }
+            if !self.running || self.paused || self.key_wait.is_some() {
+                break;
+            }
+        }
+        self.stats.pc.store(self.mem.get_pc(), Ordering::Relaxed);
+        self.stats.instructions.fetch_add(executed as u64, Ordering::Relaxed);
+        if self.frame_dirty && self.last_frame.elapsed().as_secs_f64() >= FRAME_INTERVAL {
+            self.publish_frame();
+        }
+        return executed;
+    }
+
+    fn execute(&mut self) {
+        let instruction = self.mem.fetch_decoded();
+        let pc = self.mem.get_pc() as u16;
+        self.mem.execute(pc, pc);
+        self.mem.execute(pc, pc.wrapping_add(1));
+        if let Some(ref mut profiler) = self.profiler {
+            profiler.record(self.mem.get_pc(), &instruction);
+        }
+        use Instruction::*;
+        match instruction {
+            ClearDisplay => {
+                self.display.clear();
+                self.frame_dirty = true;
+                self.mem.inc_pc();
+            },
+            ReturnFromSubroutine => {
+                if let Some(addr) = self.mem.pop_stack() {
                     self.mem.set_pc(addr);
-                },
-                SkipEq(reg_idx, byte) => {
-                    if self.mem.get_reg(reg_idx) == byte {
-                        self.mem.double_inc_pc();
-                    } else {
-                        self.mem.inc_pc();
-                    }
-                },
-                SkipNeq(reg_idx, byte) => {
-                    if self.mem.get_reg(reg_idx) != byte {
-                        self.mem.double_inc_pc();
-                    } else {
-                        self.mem.inc_pc();
-                    }
-                },
-                SkipRegsEq(reg_idx, reg_idy) => {
-                    if self.mem.get_reg(reg_idx) == self.mem.get_reg(reg_idy) {
-                        self.mem.double_inc_pc();
-                    } else {
-                        self.mem.inc_pc();
-                    }
-                },
-                SetReg(reg_idx, byte) => {
-                    self.mem.set_reg(reg_idx, byte);
-                    self.mem.inc_pc();
-                },
-                AddReg(reg_idx, byte) => {
-                    let sum = (Wrapping(self.mem.get_reg(reg_idx)) + Wrapping(byte)).0;
-                    self.mem.set_reg(reg_idx, sum);
-                    self.mem.inc_pc();
-                },
-                SetRegFromReg(reg_idx, reg_idy) => {
-                    self.mem.set_reg(reg_idx, self.mem.get_reg(reg_idy));
-                    self.mem.inc_pc();
-                },
-                BitwiseOr(reg_idx, reg_idy) => {
-                    let or = self.mem.get_reg(reg_idx) | self.mem.get_reg(reg_idy);
-                    self.mem.set_reg(reg_idx, or);
-                    self.mem.inc_pc();
-                },
-                BitwiseAnd(reg_idx, reg_idy) => {
-                    let and = self.mem.get_reg(reg_idx) & self.mem.get_reg(reg_idy);
-                    self.mem.set_reg(reg_idx, and);
-                    self.mem.inc_pc();
-                },
-                BitwiseXor(reg_idx, reg_idy) => {
-                    let xor = self.mem.get_reg(reg_idx) ^ self.mem.get_reg(reg_idy);
-                    self.mem.set_reg(reg_idx, xor);
-                    self.mem.inc_pc();
-                },
-                AddRegWithCarry(reg_idx, reg_idy) => {
-                    let sum = (Wrapping(self.mem.get_reg(reg_idx)) + Wrapping(self.mem.get_reg(reg_idy))).0;
-                    let carry = self.mem.get_reg(reg_idx) as u16 + self.mem.get_reg(reg_idy) as u16 > 255;
-                    if carry {
-                        self.mem.set_reg(0x0F, 0x01);
-                    } else {
-                        self.mem.set_reg(0x0F, 0x00);
-                    }
-                    self.mem.set_reg(reg_idx, sum);
-                    self.mem.inc_pc();
-                },
-                SubReg(reg_idx, reg_idy) => {
-                    let diff = (Wrapping(self.mem.get_reg(reg_idx)) - Wrapping(self.mem.get_reg(reg_idy))).0;
-                    if self.mem.get_reg(reg_idx) > self.mem.get_reg(reg_idy) {
-                        self.mem.set_reg(0x0F, 0x01);
-                    } else {
-                        self.mem.set_reg(0x0F, 0x00);
-                    }
-                    self.mem.set_reg(reg_idx, diff);
-                    self.mem.inc_pc();
-                },
-                ShiftRight(reg_idx, _) => {
-                    let lsb = self.mem.get_reg(reg_idx) & 0x01;
-                    self.mem.set_reg(0x0F, lsb);
-                    self.mem.set_reg(reg_idx, self.mem.get_reg(reg_idx) / 2);
-                    self.mem.inc_pc();
-                },
-                SubRegBackwards(reg_idx, reg_idy) => {
-                    let diff = (Wrapping(self.mem.get_reg(reg_idy)) - Wrapping(self.mem.get_reg(reg_idx))).0;
-                    if self.mem.get_reg(reg_idy) > self.mem.get_reg(reg_idx) {
-                        self.mem.set_reg(0x0F, 0x01);
-                    } else {
-                        self.mem.set_reg(0x0F, 0x00);
-                    }
-                    self.mem.set_reg(reg_idx, diff);
-                    self.mem.inc_pc();
-                },
-                ShiftLeft(reg_idx, _) => {
-                    let msb = (self.mem.get_reg(reg_idx) & 0x80) >> 7;
-                    self.mem.set_reg(0x0F, msb);
-                    self.mem.set_reg(reg_idx, self.mem.get_reg(reg_idx) * 2);
-                },
-                SkipRegsNeq(reg_idx, reg_idy) => {
-                    if self.mem.get_reg(reg_idx) != self.mem.get_reg(reg_idy) {
-                        self.mem.double_inc_pc();
-                    } else {
-                        self.mem.inc_pc();
-                    }
-                },
-                SetI(addr) => {
-                    self.mem.set_ireg(addr);
-                    self.mem.inc_pc();
-                },
-                JumpToLocRel(offset) => {
-                    self.mem.set_pc(offset + self.mem.get_reg(0x00) as u16);
-                },
-                Random(reg_idx, byte) => {
-                    use rand::Rng;
-                    let mut rng = rand::thread_rng();
-                    let random: u8 = rng.gen();
-                    self.mem.set_reg(reg_idx, random & byte);
-                    self.mem.inc_pc();
-                },
-                DrawSprite(reg_idx, reg_idy, n) => {
-                    let x = self.mem.get_reg(reg_idx);
-                    let y = self.mem.get_reg(reg_idy);
-                    let mut bytes = Vec::new();
-                    let i = self.mem.get_ireg();
-                    for offset in 0..n {
-                        let addr = i + offset as u16;
-                        let byte = self.mem.get(addr);
-                        bytes.push(byte);
-                    }
-                    let occluded = self.display_byte_sprite(x as usize, y as usize, bytes);
-                    if occluded {
-                        self.mem.set_reg(0x0F, 0x01);
-                    } else {
-                        self.mem.set_reg(0x0F, 0x00);
-                    }
-                    self.mem.inc_pc();
-                },
-                SkipIfPressed(reg_idx) => {
-                    let key_id = self.mem.get_reg(reg_idx);
-                    if self.game.lock().unwrap().get_key_state(key_id) == KeyState::Pressed {
-                        self.mem.double_inc_pc();
-                    } else {
-                        self.mem.inc_pc();
-                    }
-                },
-                SkipIfNotPressed(reg_idx) => {
-                    let key_id = self.mem.get_reg(reg_idx);
-                    if self.game.lock().unwrap().get_key_state(key_id) == KeyState::Released {
-                        self.mem.double_inc_pc();
-                    } else {
-                        self.mem.inc_pc();
-                    }
-                },
-                SetRegToDelayTimer(reg_idx) => {
-                    self.mem.set_reg(reg_idx, self.mem.get_dt_reg());
-                    self.mem.inc_pc();
-                },
-                BlockOnKeypress(reg_idx) => {
-                    let mut blocked = true;
-                    while blocked {
-                        {
-                            let state = self.game.lock().unwrap();
-                            for key_id in 0..16 {
-                                if state.get_key_state(key_id) == KeyState::Pressed {
-                                    println!("Found a key press!");
-                                    blocked = false;
-                                    self.mem.set_reg(reg_idx, key_id);
-                                }
-                            }
-                        }
-                        thread::sleep(time::Duration::from_millis(100));
-                    }
-                    self.mem.inc_pc();
-                },
-                SetDelayTimer(reg_idx) => {
-                    self.mem.set_dt_reg(self.mem.get_reg(reg_idx));
-                    self.mem.inc_pc();
-                },
-                SetSoundTimer(reg_idx) => {
-                    self.mem.set_st_reg(self.mem.get_reg(reg_idx));
-                    self.mem.inc_pc();
-                },
-                AddI(reg_idx) => {
-                    let sum = self.mem.get_reg(reg_idx) as u16 + self.mem.get_ireg();
-                    self.mem.set_ireg(sum);
+                } else {
+                    self.fail(format!("Return with an empty stack at {:04X}", self.mem.get_pc()));
+                }
+            },
+            ScrollDown(rows) => {
+                self.display.scroll_down(rows as usize);
+                self.frame_dirty = true;
+                self.mem.inc_pc();
+            },
+            ScrollUp(rows) => {
+                self.display.scroll_up(rows as usize);
+                self.frame_dirty = true;
+                self.mem.inc_pc();
+            },
+            ScrollRight => {
+                self.display.scroll_right(4);
+                self.frame_dirty = true;
+                self.mem.inc_pc();
+            },
+            ScrollLeft => {
+                self.display.scroll_left(4);
+                self.frame_dirty = true;
+                self.mem.inc_pc();
+            },
+            Exit => {
+                self.running = false;
+            },
+            LowRes => {
+                self.display.set_hires(false);
+                self.frame_dirty = true;
+                self.mem.inc_pc();
+            },
+            HighRes => {
+                self.display.set_hires(true);
+                self.frame_dirty = true;
+                self.mem.inc_pc();
+            },
+            JumpToLoc(addr) => self.mem.set_pc(addr),
+            CallSubroutine(addr) => {
+                self.mem.push_stack(self.mem.get_pc() as u16 + 2);
+                self.mem.set_pc(addr);
+            },
+            SkipEq(reg_idx, byte) => {
+                if self.mem.get_reg(reg_idx) == byte {
+                    self.mem.double_inc_pc();
+                } else {
                     self.mem.inc_pc();
-                },
-                LoadSprite(reg_idx) => {
-                    let num = self.mem.get_reg(reg_idx) as u16;
-                    if num <= 0xF {
-                        self.mem.set_ireg(num * 5);
-                    }
+                }
+            },
+            SkipNeq(reg_idx, byte) => {
+                if self.mem.get_reg(reg_idx) != byte {
+                    self.mem.double_inc_pc();
+                } else {
                     self.mem.inc_pc();
-                },
-                ToDecimal(reg_idx) => {
-                    let num = self.mem.get_reg(reg_idx);
-                    let hundreds: u16 = (num / 100).into();
-                    let tens: u16 = ((num % 100) / 10).into();
-                    let ones: u16 = (num % 10).into();
-                    let bcd = ((hundreds << 8) & 0x0F00) | ((tens << 4) & 0x00F0) | (ones & 0x000F);
-                    self.mem.set_ireg(bcd); 
+                }
+            },
+            SkipRegsEq(reg_idx, reg_idy) => {
+                if self.mem.get_reg(reg_idx) == self.mem.get_reg(reg_idy) {
+                    self.mem.double_inc_pc();
+                } else {
                     self.mem.inc_pc();
                 }
-                CopyRegsIntoMemory(reg_idx) => {
-                    let mut loc = self.mem.get_ireg();
-                    for reg_id in 0..reg_idx+1 {
-                        self.mem.set(loc, self.mem.get_reg(reg_id));
-                        loc += 1;
-                    }
-                    self.mem.set_ireg(loc);
+            },
+            SetReg(reg_idx, byte) => {
+                self.mem.set_reg(reg_idx, byte);
+                self.mem.inc_pc();
+            },
+            AddReg(reg_idx, byte) => {
+                let sum = (Wrapping(self.mem.get_reg(reg_idx)) + Wrapping(byte)).0;
+                self.mem.set_reg(reg_idx, sum);
+                self.mem.inc_pc();
+            },
+            SaveRegRange(reg_idx, reg_idy) => {
+                let mut loc = self.mem.get_ireg();
+                for reg_id in reg_range(reg_idx, reg_idy) {
+                    self.mem.set(loc, self.mem.get_reg(reg_id));
+                    loc = loc.wrapping_add(1);
+                }
+                self.mem.inc_pc();
+            },
+            LoadRegRange(reg_idx, reg_idy) => {
+                let mut loc = self.mem.get_ireg();
+                for reg_id in reg_range(reg_idx, reg_idy) {
+                    let value = self.mem.read(loc);
+                    self.mem.set_reg(reg_id, value);
+                    loc = loc.wrapping_add(1);
+                }
+                self.mem.inc_pc();
+            },
+            SetRegFromReg(reg_idx, reg_idy) => {
+                self.mem.set_reg(reg_idx, self.mem.get_reg(reg_idy));
+                self.mem.inc_pc();
+            },
+            BitwiseOr(reg_idx, reg_idy) => {
+                let or = self.mem.get_reg(reg_idx) | self.mem.get_reg(reg_idy);
+                self.mem.set_reg(reg_idx, or);
+                self.mem.inc_pc();
+            },
+            BitwiseAnd(reg_idx, reg_idy) => {
+                let and = self.mem.get_reg(reg_idx) & self.mem.get_reg(reg_idy);
+                self.mem.set_reg(reg_idx, and);
+                self.mem.inc_pc();
+            },
+            BitwiseXor(reg_idx, reg_idy) => {
+                let xor = self.mem.get_reg(reg_idx) ^ self.mem.get_reg(reg_idy);
+                self.mem.set_reg(reg_idx, xor);
+                self.mem.inc_pc();
+            },
+            AddRegWithCarry(reg_idx, reg_idy) => {
+                let sum = (Wrapping(self.mem.get_reg(reg_idx)) + Wrapping(self.mem.get_reg(reg_idy))).0;
+                let carry = self.mem.get_reg(reg_idx) as u16 + self.mem.get_reg(reg_idy) as u16 > 255;
+                if carry {
+                    self.mem.set_reg(0x0F, 0x01);
+                } else {
+                    self.mem.set_reg(0x0F, 0x00);
+                }
+                self.mem.set_reg(reg_idx, sum);
+                self.mem.inc_pc();
+            },
+            SubReg(reg_idx, reg_idy) => {
+                let diff = (Wrapping(self.mem.get_reg(reg_idx)) - Wrapping(self.mem.get_reg(reg_idy))).0;
+                if self.mem.get_reg(reg_idx) > self.mem.get_reg(reg_idy) {
+                    self.mem.set_reg(0x0F, 0x01);
+                } else {
+                    self.mem.set_reg(0x0F, 0x00);
+                }
+                self.mem.set_reg(reg_idx, diff);
+                self.mem.inc_pc();
+            },
+            ShiftRight(reg_idx, _) => {
+                let lsb = self.mem.get_reg(reg_idx) & 0x01;
+                self.mem.set_reg(0x0F, lsb);
+                self.mem.set_reg(reg_idx, self.mem.get_reg(reg_idx) / 2);
+                self.mem.inc_pc();
+            },
+            SubRegBackwards(reg_idx, reg_idy) => {
+                let diff = (Wrapping(self.mem.get_reg(reg_idy)) - Wrapping(self.mem.get_reg(reg_idx))).0;
+                if self.mem.get_reg(reg_idy) > self.mem.get_reg(reg_idx) {
+                    self.mem.set_reg(0x0F, 0x01);
+                } else {
+                    self.mem.set_reg(0x0F, 0x00);
+                }
+                self.mem.set_reg(reg_idx, diff);
+                self.mem.inc_pc();
+            },
+            ShiftLeft(reg_idx, _) => {
+                let msb = (self.mem.get_reg(reg_idx) & 0x80) >> 7;
+                self.mem.set_reg(0x0F, msb);
+                self.mem.set_reg(reg_idx, self.mem.get_reg(reg_idx) * 2);
+            },
+            SkipRegsNeq(reg_idx, reg_idy) => {
+                if self.mem.get_reg(reg_idx) != self.mem.get_reg(reg_idy) {
+                    self.mem.double_inc_pc();
+                } else {
                     self.mem.inc_pc();
-                },
-                CopyRegsFromMemory(reg_idx) => {
-                    let mut loc = self.mem.get_ireg();
-                    for reg_id in 0..reg_idx+1 {
-                        self.mem.set_reg(reg_id, self.mem.get(loc));
-                        loc += 1;
-                    }
-                    self.mem.set_ireg(loc);
+                }
+            },
+            SetI(addr) => {
+                self.mem.set_ireg(addr);
+                self.mem.inc_pc();
+            },
+            LongSetI => {
+                let pc = self.mem.get_pc() as u16;
+                // The address that follows is part of the instruction rather than data
+                self.mem.execute(pc, pc.wrapping_add(2));
+                self.mem.execute(pc, pc.wrapping_add(3));
+                self.mem.set_ireg(self.mem.get_word(pc.wrapping_add(2)));
+                self.mem.set_pc(pc.wrapping_add(4));
+            },
+            JumpToLocRel(offset) => {
+                self.mem.set_pc(offset + self.mem.get_reg(0x00) as u16);
+            },
+            Random(reg_idx, byte) => {
+                use rand::Rng;
+                let mut rng = rand::thread_rng();
+                let random: u8 = rng.gen();
+                self.mem.set_reg(reg_idx, random & byte);
+                self.mem.inc_pc();
+            },
+            DrawSprite(reg_idx, reg_idy, n) => {
+                self.draw_sprite(reg_idx, reg_idy, n as u16, 1);
+                self.mem.inc_pc();
+            },
+            DrawLargeSprite(reg_idx, reg_idy) => {
+                // A 16x16 sprite stored as 16 two-byte rows
+                self.draw_sprite(reg_idx, reg_idy, 32, 2);
+                self.mem.inc_pc();
+            },
+            SkipIfPressed(reg_idx) => {
+                let key_id = self.mem.get_reg(reg_idx);
+                if self.game.lock().unwrap().get_key_state(key_id) == KeyState::Pressed {
+                    self.mem.double_inc_pc();
+                } else {
                     self.mem.inc_pc();
-                },
-                InvalidInstruction(byte_code) => {
-                    println!("Error: {:X} unrecognized", byte_code);
-                    return
                 }
-                _ => {
-                    println!("{:?} not implemented yet", instruction);
+            },
+            SkipIfNotPressed(reg_idx) => {
+                let key_id = self.mem.get_reg(reg_idx);
+                if self.game.lock().unwrap().get_key_state(key_id) == KeyState::Released {
+                    self.mem.double_inc_pc();
+                } else {
                     self.mem.inc_pc();
-                },
+                }
+            },
+            SetRegToDelayTimer(reg_idx) => {
+                self.mem.set_reg(reg_idx, self.mem.get_dt_reg());
+                self.mem.inc_pc();
+            },
+            BlockOnKeypress(reg_idx) => {
+                // Show what has been drawn so far while we wait
+                if self.frame_dirty {
+                    self.publish_frame();
+                }
+                self.game.lock().unwrap().take_key_edges();
+                self.key_wait = Some(KeyWait {
+                    reg_idx,
+                    armed: 0,
+                });
+                self.stats.waiting_for_key.store(true, Ordering::Relaxed);
+            },
+            SetDelayTimer(reg_idx) => {
+                self.mem.set_dt_reg(self.mem.get_reg(reg_idx));
+                self.mem.inc_pc();
+            },
+            SetSoundTimer(reg_idx) => {
+                self.mem.set_st_reg(self.mem.get_reg(reg_idx));
+                self.mem.inc_pc();
+            },
+            SelectPlanes(planes) => {
+                self.display.set_planes(planes);
+                self.mem.inc_pc();
+            },
+            LoadAudioPattern => {
+                let i = self.mem.get_ireg();
+                let mut pattern = [0x00; 16];
+                for (offset, byte) in pattern.iter_mut().enumerate() {
+                    *byte = self.mem.read(i.wrapping_add(offset as u16));
+                }
+                self.mem.tone.pattern = Some(pattern);
+                self.mem.inc_pc();
+            },
+            SetPitch(reg_idx) => {
+                self.mem.tone.pitch = self.mem.get_reg(reg_idx);
+                self.mem.inc_pc();
+            },
+            AddI(reg_idx) => {
+                let sum = self.mem.get_ireg().wrapping_add(self.mem.get_reg(reg_idx) as u16);
+                self.mem.set_ireg(sum);
+                self.mem.inc_pc();
+            },
+            LoadSprite(reg_idx) => {
+                let num = self.mem.get_reg(reg_idx) as u16;
+                if num <= 0xF {
+                    self.mem.set_ireg(num * 5);
+                }
+                self.mem.inc_pc();
+            },
+            LoadLargeSprite(reg_idx) => {
+                let num = self.mem.get_reg(reg_idx) as u16;
+                if num <= 0xF {
+                    self.mem.set_ireg(LARGE_FONT_ADDR + num * 10);
+                }
+                self.mem.inc_pc();
+            },
+            ToDecimal(reg_idx) => {
+                let num = self.mem.get_reg(reg_idx);
+                let hundreds: u16 = (num / 100).into();
+                let tens: u16 = ((num % 100) / 10).into();
+                let ones: u16 = (num % 10).into();
+                let bcd = ((hundreds << 8) & 0x0F00) | ((tens << 4) & 0x00F0) | (ones & 0x000F);
+                self.mem.set_ireg(bcd); 
+                self.mem.inc_pc();
+            }
+            CopyRegsIntoMemory(reg_idx) => {
+                let mut loc = self.mem.get_ireg();
+                for reg_id in 0..reg_idx+1 {
+                    self.mem.set(loc, self.mem.get_reg(reg_id));
+                    loc = loc.wrapping_add(1);
+                }
+                self.mem.set_ireg(loc);
+                self.mem.inc_pc();
+            },
+            CopyRegsFromMemory(reg_idx) => {
+                let mut loc = self.mem.get_ireg();
+                for reg_id in 0..reg_idx+1 {
+                    let value = self.mem.read(loc);
+                    self.mem.set_reg(reg_id, value);
+                    loc = loc.wrapping_add(1);
+                }
+                self.mem.set_ireg(loc);
+                self.mem.inc_pc();
+            },
+            SaveFlags(reg_idx) => {
+                for reg_id in 0..=reg_idx.min(self.mem.last_flag()) {
+                    self.mem.set_flag(reg_id, self.mem.get_reg(reg_id));
+                }
+                self.mem.inc_pc();
+            },
+            LoadFlags(reg_idx) => {
+                for reg_id in 0..=reg_idx.min(self.mem.last_flag()) {
+                    self.mem.set_reg(reg_id, self.mem.get_flag(reg_id));
+                }
+                self.mem.inc_pc();
+            },
+            InvalidInstruction(byte_code) => {
+                self.fail(format!("Invalid opcode {:04X} at {:04X}", byte_code, self.mem.get_pc()));
+            }
+        }
+        if let Some(ref mut smc) = self.mem.smc {
+            for event in smc.take_events() {
+                self.report_smc(event);
             }
-            thread::sleep(time::Duration::from_millis(3));
         }
     }
 
-    fn display_byte_sprite(&mut self, x: usize, y: usize, bytes: Vec<u8>) -> bool {
+    // Draws `len` bytes from I at (Vx, Vy), `row_width` bytes to a row, and
+    // sets VF if any pixel was turned off
+    fn draw_sprite(&mut self, reg_idx: u8, reg_idy: u8, len: u16, row_width: usize) {
+        let x = self.mem.get_reg(reg_idx);
+        let y = self.mem.get_reg(reg_idy);
+        let mut addr = self.mem.get_ireg();
+        let planes = self.display.get_planes();
         let mut occluded = false;
-        for (i, byte) in bytes.iter().enumerate() {
+        // Each selected plane consumes its own copy of the sprite data in turn
+        for plane in [0x01, 0x02].iter().filter(|plane| planes & *plane != 0) {
+            let mut bytes = Vec::new();
+            for _ in 0..len {
+                bytes.push(self.mem.read(addr));
+                addr = addr.wrapping_add(1);
+            }
+            occluded |= self.display_byte_sprite(x as usize, y as usize, bytes, row_width, *plane);
+        }
+        self.frame_dirty = true;
+        if occluded {
+            self.mem.set_reg(0x0F, 0x01);
+        } else {
+            self.mem.set_reg(0x0F, 0x00);
+        }
+    }
+
+    // Stops the program, leaving the reason for the frontend to show
+    fn fail(&mut self, message: String) {
+        eprintln!("Error: {}", message);
+        *self.stats.error.lock().unwrap() = Some(message);
+        self.running = false;
+    }
+
+    // Like the VIP, FX0A completes when a key is released after being pressed
+    fn poll_key_wait(&mut self, mut wait: KeyWait) {
+        let (pressed, released) = self.game.lock().unwrap().take_key_edges();
+        wait.armed |= pressed;
+        let completed = wait.armed & released;
+        if completed != 0 {
+            self.mem.set_reg(wait.reg_idx, completed.trailing_zeros() as u8);
+            self.mem.inc_pc();
+            self.key_wait = None;
+        } else {
+            self.key_wait = Some(wait);
+        }
+    }
+
+    // Hands the current display to the frontend
+    fn publish_frame(&mut self) {
+        let frame = self.frames.buffer();
+        self.display.copy_to(frame);
+        frame.number = self.frame_count;
+        self.frames.publish();
+        self.frame_dirty = false;
+        self.last_frame = time::Instant::now();
+    }
+
+    // Draws rows of `row_width` bytes each, XORing them onto the given plane
+    // The sprite origin always wraps, but pixels past the edge are clipped
+    // unless the wrap_sprites quirk is set
+    fn display_byte_sprite(&mut self, x: usize, y: usize, bytes: Vec<u8>, row_width: usize, plane: u8) -> bool {
+        let mut occluded = false;
+        for (idx, byte) in bytes.iter().enumerate() {
+            let i = idx / row_width;
+            let col = (idx % row_width) * 8;
             for j in 0..8 {
                 let bit = (*byte & (0x80 >> j)) >> (7-j);
-                let mut pixel_state: bool = false;
-                match bit {
-                    0x00 => pixel_state = false,
-                    0x01 => pixel_state = true,
-                    _ => eprintln!("bit is in invalid state"),
-                }
-                let mut game = self.game.lock().unwrap();
-                let prev_pixel = game.get_pixel(x+j, y+i);
-                if prev_pixel == true && pixel_state ^ prev_pixel == false {
-                    occluded = true;
+                if bit == 0x01 {
+                    let px = x % self.display.width() + col + j;
+                    let py = y % self.display.height() + i;
+                    if !self.quirks.wrap_sprites && (px >= self.display.width() || py >= self.display.height()) {
+                        continue;
+                    }
+                    occluded |= self.display.xor_pixel(px, py, plane);
                 }
-                game.set_pixel(x+j, y+i, pixel_state ^ prev_pixel);
             }
         }
         return occluded;
     }
 }
 
+// The SCHIP large font is stored directly after the 5-byte font
+const LARGE_FONT_ADDR: u16 = 0x50;
+// XO-CHIP extends the address space to the full 16 bits
+const RAM_SIZE: usize = 0x10000;
+
+// Registers are visited from Vx to Vy, backwards if x > y
+fn reg_range(reg_idx: u8, reg_idy: u8) -> Vec<u8> {
+    if reg_idx <= reg_idy {
+        (reg_idx..=reg_idy).collect()
+    } else {
+        (reg_idy..=reg_idx).rev().collect()
+    }
+}
+
 struct Memory {
-    ram: [u8; 0xFFF],
+    ram: Vec<u8>,
+    // Decides which instructions exist
+    platform: Platform,
     program_addr: u16,
     program_counter: usize,
     stack: Vec<u16>,
     registers: [u8; 16],
     i_reg: u16,
-    dt_reg: Arc<AtomicU8>,
-    st_reg: Arc<AtomicU8>,
+    dt_reg: u8,
+    st_reg: u8,
+    // RPL user flags, eight on SCHIP and sixteen on XO-CHIP
+    flags: [u8; 16],
+    // XO-CHIP audio pattern and pitch
+    tone: Tone,
+    // Flags from the coverage module for each address, if they are being kept
+    coverage: Option<Vec<u8>>,
+    smc: Option<SmcMonitor>,
+    // Each address's instruction as last decoded, cleared when either of its
+    // bytes is written. None turns the cache off.
+    decoded: Option<Vec<Option<Instruction>>>,
 }
 
 impl Memory {
-    fn new(program: Vec<u16>) -> Self {
+    fn new(program: &[u8], platform: Platform) -> Result<Self, String> {
+        let start_addr = platform.start_addr(program);
         let mut mem = Memory {
-            ram: [0x00; 0xFFF],
-            program_addr: 0x200,
-            program_counter: 0x200,
+            ram: vec![0x00; RAM_SIZE],
+            platform,
+            program_addr: PROGRAM_ADDR,
+            program_counter: start_addr as usize,
             stack: Vec::new(),
             registers: [0x00; 16],
             i_reg: 0x0000,
-            dt_reg: Arc::new(AtomicU8::new(0x00)),
-            st_reg: Arc::new(AtomicU8::new(0x00)),
+            dt_reg: 0x00,
+            st_reg: 0x00,
+            flags: [0x00; 16],
+            tone: Tone::new(),
+            coverage: None,
+            smc: None,
+            decoded: Some(vec![None; RAM_SIZE]),
         };
-        mem.load_program(program);
+        mem.load_program(program)?;
         mem.init_sprites();
-        return mem;
+        return Ok(mem);
     }
 
     fn num_to_sprite(&self, num: usize) -> [u8; 5] {
@@ -457,38 +1173,97 @@ impl Memory {
         }
     }
 
+    fn num_to_large_sprite(&self, num: usize) -> [u8; 10] {
+        match num {
+            0x0 => [0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C],
+            0x1 => [0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C],
+            0x2 => [0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF],
+            0x3 => [0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C],
+            0x4 => [0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06],
+            0x5 => [0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C],
+            0x6 => [0x3E, 0x7C, 0xC0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C],
+            0x7 => [0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60],
+            0x8 => [0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C],
+            0x9 => [0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C],
+            0xA => [0x18, 0x3C, 0x66, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3],
+            0xB => [0xFC, 0xFE, 0xC3, 0xC3, 0xFE, 0xFE, 0xC3, 0xC3, 0xFE, 0xFC],
+            0xC => [0x3C, 0x7E, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0x7E, 0x3C],
+            0xD => [0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC],
+            0xE => [0xFF, 0xFF, 0xC0, 0xC0, 0xFE, 0xFE, 0xC0, 0xC0, 0xFF, 0xFF],
+            0xF => [0xFF, 0xFF, 0xC0, 0xC0, 0xFE, 0xFE, 0xC0, 0xC0, 0xC0, 0xC0],
+            _ => [0x00; 10],
+        }
+    }
+
     fn init_sprites(&mut self) {
-        for i in 0x0..0xF as usize {
+        for i in 0x0..=0xF {
             let sprite = self.num_to_sprite(i);
             for (j, byte) in sprite.iter().enumerate() {
                 self.ram[i*5 + j] = *byte;
             }
+            let large_sprite = self.num_to_large_sprite(i);
+            for (j, byte) in large_sprite.iter().enumerate() {
+                self.ram[LARGE_FONT_ADDR as usize + i*10 + j] = *byte;
+            }
         }
     }
 
-    fn load_program(&mut self, program: Vec<u16>) {
-        let mut addr = self.program_addr;
-        for instruction in program {
-            let first_byte = ((instruction & 0xFF00) >> 8) as u8;
-            let second_byte = (instruction & 0x00FF) as u8;
-            self.set(addr, first_byte);
-            self.set(addr+1, second_byte);
-            addr += 2;
+    fn load_program(&mut self, program: &[u8]) -> Result<(), String> {
+        self.platform.check_program_size(program.len())?;
+        for (offset, byte) in program.iter().enumerate() {
+            let addr = (self.program_addr as usize).checked_add(offset)
+                .filter(|addr| *addr < self.ram.len())
+                .ok_or("program runs past the end of memory")?;
+            self.poke(addr as u16, *byte);
         }
+        return Ok(());
     }
 
     fn fetch_instruction(&self) -> u16 {
-        let first_byte = self.ram[self.program_counter];
-        let second_byte = self.ram[self.program_counter + 1];
+        return self.get_word(self.program_counter as u16);
+    }
+
+    // The instruction at the program counter, decoding it only if it hasn't
+    // been since it was last written
+    fn fetch_decoded(&mut self) -> Instruction {
+        let pc = self.program_counter as u16;
+        if let Some(instruction) = self.decoded.as_ref().and_then(|decoded| decoded[pc as usize]) {
+            return instruction;
+        }
+        let instruction = decode(self.get_word(pc), self.platform);
+        if let Some(ref mut decoded) = self.decoded {
+            decoded[pc as usize] = Some(instruction);
+        }
+        return instruction;
+    }
+
+    // Drops the instructions that include the byte at `addr`
+    fn invalidate(&mut self, addr: u16) {
+        if let Some(ref mut decoded) = self.decoded {
+            decoded[addr as usize] = None;
+            decoded[addr.wrapping_sub(1) as usize] = None;
+        }
+    }
+
+    fn get_word(&self, addr: u16) -> u16 {
+        let first_byte = self.get(addr);
+        let second_byte = self.get(addr.wrapping_add(1));
         return ((first_byte as u16) << 8) | (second_byte as u16);
     }
 
     fn inc_pc(&mut self) {
-        self.program_counter += 2;
+        self.set_pc((self.program_counter as u16).wrapping_add(2));
     }
 
+    // Skips the next instruction, which is two words long if it is XO-CHIP's F000 NNNN
     fn double_inc_pc(&mut self) {
-        self.program_counter += 4;
+        let pc = self.program_counter as u16;
+        let long = self.platform.supports(Extension::XoChip) && self.get_word(pc.wrapping_add(2)) == 0xF000;
+        if long {
+            self.set_pc(pc.wrapping_add(6));
+        } else {
+            self.set_pc(pc.wrapping_add(4));
+        }
     }
 
     fn get_reg(&self, reg: u8) -> u8 {
@@ -508,37 +1283,21 @@ impl Memory {
     }
 
     fn get_dt_reg(&self) -> u8 {
-        return self.dt_reg.load(Ordering::SeqCst);
+        return self.dt_reg;
     }
 
     fn set_dt_reg(&mut self, value: u8) {
-        self.dt_reg.store(value, Ordering::SeqCst);
-        let dt_reg = self.dt_reg.clone();
-        thread::spawn(move || {
-            let mut timer = dt_reg.load(Ordering::SeqCst);
-            while timer > 0 {
-                thread::sleep(time::Duration::from_secs_f64(1.0 / 60.0));
-                dt_reg.store(timer - 1, Ordering::SeqCst);
-                timer = dt_reg.load(Ordering::SeqCst);
-            }
-        });
+        self.dt_reg = value;
     }
 
-    fn get_st_reg(&self) -> u8 {
-        return self.st_reg.load(Ordering::SeqCst);
+    fn set_st_reg(&mut self, value: u8) {
+        self.st_reg = value;
     }
 
-    fn set_st_reg(&mut self, value: u8) {
-        self.st_reg.store(value, Ordering::SeqCst);
-        let st_reg = self.st_reg.clone();
-        thread::spawn(move || {
-            let mut timer = st_reg.load(Ordering::SeqCst);
-            while timer > 0 {
-                thread::sleep(time::Duration::from_secs_f64(1.0 / 60.0));
-                st_reg.store(timer - 1, Ordering::SeqCst);
-                timer = st_reg.load(Ordering::SeqCst);
-            }
-        });
+    // Counts both timers down by one, stopping at zero
+    fn tick_timers(&mut self) {
+        self.dt_reg = self.dt_reg.saturating_sub(1);
+        self.st_reg = self.st_reg.saturating_sub(1);
     }
 
     fn get_pc(&self) -> usize {
@@ -558,11 +1317,289 @@ impl Memory {
         return self.stack.pop()
     }
 
+    fn last_flag(&self) -> u8 {
+        if self.platform.supports(Extension::XoChip) {
+            return 0xF;
+        }
+        return 7;
+    }
+
+    fn get_flag(&self, flag: u8) -> u8 {
+        return self.flags[flag as usize];
+    }
+
+    fn set_flag(&mut self, flag: u8, value: u8) {
+        self.flags[flag as usize] = value;
+    }
+
     fn get(&self, addr: u16) -> u8 {
         return self.ram[addr as usize];
     }
 
+    // Reads a byte as data on behalf of the program
+    fn read(&mut self, addr: u16) -> u8 {
+        self.mark(addr, coverage::READ);
+        return self.ram[addr as usize];
+    }
+
+    // Writes a byte on behalf of the program
     fn set(&mut self, addr: u16, value: u8) {
+        self.mark(addr, coverage::WRITTEN);
+        if let Some(ref mut smc) = self.smc {
+            smc.write(self.program_counter as u16, addr);
+        }
+        self.invalidate(addr);
+        self.ram[addr as usize] = value;
+    }
+
+    // Writes a byte from outside the program, like loading it or a cheat
+    fn poke(&mut self, addr: u16, value: u8) {
+        self.invalidate(addr);
         self.ram[addr as usize] = value;
     }
+
+    // Notes that the byte at `addr` is being run as part of the instruction at `pc`
+    fn execute(&mut self, pc: u16, addr: u16) {
+        self.mark(addr, coverage::EXECUTED);
+        if let Some(ref mut smc) = self.smc {
+            smc.execute(pc, addr);
+        }
+    }
+
+    fn mark(&mut self, addr: u16, flag: u8) {
+        if let Some(ref mut coverage) = self.coverage {
+            coverage[addr as usize] |= flag;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::{AudioSettings, WavSink};
+    use std::fs;
+    use std::process;
+
+    pub fn interpreter(program: &[u16], platform: Platform) -> Interpreter {
+        let game = Arc::new(Mutex::new(GameState::new()));
+        let (frames, _) = crate::display::frame_channel(platform);
+        let bytes = program.iter().flat_map(|word| word.to_be_bytes()).collect();
+        return Interpreter::new(bytes, game, frames, Settings::new(platform)).unwrap();
+    }
+
+    fn is_invalid(byte_code: u16, platform: Platform) -> bool {
+        return matches!(decode(byte_code, platform), Instruction::InvalidInstruction(_));
+    }
+
+    #[test]
+    fn programs_load_byte_for_byte() {
+        let mem = Memory::new(&[0x12, 0x02, 0xAB], Platform::Chip8).unwrap();
+        assert_eq!(&mem.ram[0x200..0x204], &[0x12, 0x02, 0xAB, 0x00]);
+        assert_eq!(mem.program_counter, 0x200);
+        let mem = Memory::new(&[0x12, 0x60, 0x00], Platform::Chip8Hires).unwrap();
+        assert_eq!(mem.program_counter, 0x2C0);
+    }
+
+    #[test]
+    fn programs_must_fit_in_the_platforms_memory() {
+        assert!(Memory::new(&vec![0; 0x1000 - 0x200], Platform::Chip8).is_ok());
+        assert!(Memory::new(&vec![0; 0x1000 - 0x200 + 1], Platform::Chip8).is_err());
+        assert!(Memory::new(&vec![0; 0x10000 - 0x200], Platform::XoChip).is_ok());
+        assert!(Memory::new(&vec![0; 0x10000 - 0x200 + 1], Platform::XoChip).is_err());
+        assert!(Memory::new(&vec![0; 0x20000], Platform::XoChip).is_err());
+
+        // A reload that doesn't fit stops the interpreter with the reason
+        let mut interpreter = interpreter(&[0x1200], Platform::Chip8);
+        interpreter.run_command(Command::Load(vec![0; 0x2000], Settings::new(Platform::Chip8)));
+        assert!(!interpreter.is_running());
+        assert!(interpreter.stats.error.lock().unwrap().is_some());
+    }
+
+    #[test]
+    fn extensions_only_decode_on_their_platforms() {
+        for byte_code in [0x00FF, 0x00C4, 0xF130, 0xF275].iter() {
+            assert!(is_invalid(*byte_code, Platform::Chip8), "{:04X}", byte_code);
+            assert!(!is_invalid(*byte_code, Platform::SuperChip), "{:04X}", byte_code);
+            assert!(!is_invalid(*byte_code, Platform::XoChip), "{:04X}", byte_code);
+        }
+        for byte_code in [0xF000, 0xF201, 0xF002, 0x5122, 0x00D4].iter() {
+            assert!(is_invalid(*byte_code, Platform::SuperChip), "{:04X}", byte_code);
+            assert!(!is_invalid(*byte_code, Platform::XoChip), "{:04X}", byte_code);
+        }
+        assert!(matches!(decode(0x0230, Platform::Chip8Hires), Instruction::ClearDisplay));
+        assert!(is_invalid(0x0230, Platform::Chip8));
+        assert!(is_invalid(0x0230, Platform::SuperChip));
+    }
+
+    #[test]
+    fn xochip_saves_all_sixteen_flags() {
+        // V0..VF = 1..16, save them all, clear them, then load them back
+        let mut program: Vec<u16> = (0..16).map(|x| 0x6000 | x << 8 | (x + 1)).collect();
+        program.push(0xFF75);
+        program.extend((0..16).map(|x| 0x6000 | x << 8));
+        program.push(0xFF85);
+        let steps = program.len() as u32;
+        for (platform, saved) in [(Platform::SuperChip, 8), (Platform::XoChip, 16)].iter() {
+            let mut interpreter = interpreter(&program, *platform);
+            interpreter.run(steps);
+            let registers = interpreter.snapshot().registers;
+            for (x, value) in registers.iter().enumerate() {
+                let expected = if x < *saved { x as u8 + 1 } else { 0 };
+                assert_eq!(*value, expected, "V{:X} on {:?}", x, platform);
+            }
+        }
+    }
+
+    #[test]
+    fn skips_wrap_around_the_end_of_memory() {
+        let mut mem = Memory::new(&[], Platform::XoChip).unwrap();
+        mem.set_pc(0xFFFE);
+        mem.double_inc_pc();
+        assert_eq!(mem.get_pc(), 0x0002);
+        mem.set_pc(0xFFFE);
+        mem.inc_pc();
+        assert_eq!(mem.get_pc(), 0x0000);
+        // Skipping a long I = NNNN that straddles the end
+        mem.poke(0x0000, 0xF0);
+        mem.poke(0x0001, 0x00);
+        mem.set_pc(0xFFFE);
+        mem.double_inc_pc();
+        assert_eq!(mem.get_pc(), 0x0004);
+    }
+
+    #[test]
+    fn long_loads_wrap_around_the_end_of_memory() {
+        let mut interpreter = interpreter(&[], Platform::XoChip);
+        for (addr, byte) in [(0xFFFC, 0xF0), (0xFFFD, 0x00), (0xFFFE, 0xAB), (0xFFFF, 0xCD)].iter() {
+            interpreter.mem.poke(*addr, *byte);
+        }
+        interpreter.mem.set_pc(0xFFFC);
+        interpreter.step();
+        assert_eq!(interpreter.snapshot().i_reg, 0xABCD);
+        assert_eq!(interpreter.snapshot().pc, 0x0000);
+    }
+
+    #[test]
+    fn large_sprites_need_schip() {
+        assert!(matches!(decode(0xD120, Platform::Chip8), Instruction::DrawSprite(1, 2, 0)));
+        assert!(matches!(decode(0xD120, Platform::SuperChip), Instruction::DrawLargeSprite(1, 2)));
+    }
+
+    #[test]
+    fn runs_instructions_the_program_rewrites() {
+        // V1 = 1, then over and over: write V1 into the byte operand of the
+        // V1 += NN that follows and run it, doubling V1 each time
+        let program = [0x6101, 0xA209, 0x8010, 0xF055, 0x7100, 0x1202];
+        let mut interpreter = interpreter(&program, Platform::Chip8);
+        interpreter.run(1 + 5 * 3);
+        assert_eq!(interpreter.snapshot().registers[1], 8);
+        assert_eq!(interpreter.mem.get_word(0x208), 0x7104);
+    }
+
+    #[test]
+    fn writing_either_byte_drops_the_decoded_instruction() {
+        let mut mem = Memory::new(&[0x60, 0x01], Platform::Chip8).unwrap();
+        let decoded = |mem: &mut Memory| format!("{:?}", mem.fetch_decoded());
+        assert_eq!(decoded(&mut mem), "SetReg(0, 1)");
+        mem.set(0x200, 0x61);
+        assert_eq!(decoded(&mut mem), "SetReg(1, 1)");
+        mem.set(0x201, 0x02);
+        assert_eq!(decoded(&mut mem), "SetReg(1, 2)");
+        // Writing the neighbouring bytes keeps it cached, which shows as the
+        // stale decode of a byte changed behind the cache's back
+        mem.set(0x202, 0xFF);
+        mem.ram[0x1FF] = 0xFF;
+        mem.ram[0x201] = 0x03;
+        assert_eq!(decoded(&mut mem), "SetReg(1, 2)");
+        // Loads and cheats write with poke, which drops it too
+        mem.poke(0x201, 0x04);
+        assert_eq!(decoded(&mut mem), "SetReg(1, 4)");
+    }
+
+    #[test]
+    fn the_decode_cache_changes_nothing() {
+        for path in ["roms/INVADERS", "roms/SYZYGY", "roms/VBRIX"].iter() {
+            let program = crate::rom::read_bytes(Path::new(path)).unwrap();
+            let run = |cache: bool| {
+                let game = Arc::new(Mutex::new(GameState::new()));
+                let (frames, mut reader) = crate::display::frame_channel(Platform::Chip8);
+                let mut interpreter = Interpreter::new(program.clone(), game, frames, Settings::new(Platform::Chip8)).unwrap();
+                if !cache {
+                    interpreter.disable_decode_cache();
+                }
+                interpreter.run(50_000);
+                return (interpreter.snapshot(), reader.read().clone());
+            };
+            assert!(run(true) == run(false), "{} ran differently without the cache", path);
+        }
+    }
+
+    #[test]
+    fn timers_count_down_once_per_frame_of_instructions() {
+        // V0 = 5, DT = V0, ST = V0, then loop forever
+        let mut interpreter = interpreter(&[0x6005, 0xF015, 0xF018, 0x1206], Platform::Chip8);
+        let speed = interpreter.speed;
+        interpreter.run(3);
+        assert_eq!(interpreter.snapshot().dt_reg, 5);
+        interpreter.run(speed - 3);
+        assert_eq!(interpreter.snapshot().dt_reg, 4);
+        assert_eq!(interpreter.snapshot().st_reg, 4);
+        // Single steps only finish a frame once they add up to one
+        for _ in 0..speed - 1 {
+            interpreter.step();
+        }
+        assert_eq!(interpreter.snapshot().dt_reg, 4);
+        interpreter.step();
+        assert_eq!(interpreter.snapshot().dt_reg, 3);
+        interpreter.run(speed * 10);
+        assert_eq!(interpreter.snapshot().dt_reg, 0);
+        assert_eq!(interpreter.snapshot().st_reg, 0);
+    }
+
+    #[test]
+    fn every_frame_is_published_with_its_number() {
+        let game = Arc::new(Mutex::new(GameState::new()));
+        let (frames, mut reader) = crate::display::frame_channel(Platform::Chip8);
+        // Loop forever without drawing anything
+        let mut interpreter = Interpreter::new(vec![0x12, 0x00], game, frames, Settings::new(Platform::Chip8)).unwrap();
+        interpreter.run(interpreter.speed * 3);
+        assert_eq!(reader.read().number, 3);
+        interpreter.run(interpreter.speed);
+        assert_eq!(reader.read().number, 4);
+    }
+
+    #[test]
+    fn reset_stops_the_old_timers() {
+        let mut interpreter = interpreter(&[0x60FF, 0xF015, 0x1204], Platform::Chip8);
+        interpreter.run(2);
+        interpreter.run_command(Command::Reset);
+        assert_eq!(interpreter.snapshot().dt_reg, 0);
+        interpreter.run(interpreter.speed * 3);
+        assert_eq!(interpreter.snapshot().dt_reg, 0xFF - 3);
+    }
+
+    #[test]
+    fn beeps_in_the_wav_for_as_many_frames_as_the_sound_timer() {
+        let path = std::env::temp_dir().join(format!("chip8rs-beep-{}.wav", process::id()));
+        let settings = AudioSettings::new();
+        // V0 = 30, ST = V0, then loop forever
+        let mut interpreter = interpreter(&[0x601E, 0xF018, 0x1204], Platform::Chip8);
+        let sink = WavSink::create(path.to_str().unwrap(), settings.sample_rate).unwrap();
+        interpreter.set_audio(Audio::new(settings, Box::new(sink)));
+        let frames = 60;
+        interpreter.run(interpreter.speed * frames);
+        drop(interpreter);
+
+        let wav = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let per_frame = 735;
+        assert_eq!(settings.sample_rate as usize / 60, per_frame);
+        assert_eq!(u32::from_le_bytes([wav[40], wav[41], wav[42], wav[43]]) as usize, wav.len() - 44);
+        let samples: Vec<i16> = wav[44..].chunks(2).map(|pair| i16::from_le_bytes([pair[0], pair[1]])).collect();
+        assert_eq!(samples.len(), frames as usize * per_frame);
+        // The frame ST was set in counts, so 30 frames of beep then silence
+        let beep = samples.iter().take_while(|sample| **sample != 0).count();
+        assert_eq!(beep, 30 * per_frame);
+        assert!(samples[beep..].iter().all(|sample| *sample == 0));
+    }
 }
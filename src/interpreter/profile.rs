@@ -0,0 +1,193 @@
+use super::{disassemble, Instruction};
+use crate::platform::Platform;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::mem::{self, Discriminant};
+use std::path::Path;
+
+// How many of the busiest addresses the report lists
+const HOT_ADDRESSES: usize = 32;
+
+#[derive(Copy, Clone, Default)]
+struct Subroutine {
+    calls: u64,
+    // Instructions run from the call up to and including the return, counting nested calls
+    instructions: u64,
+}
+
+// Counts where a program spends its time, measured in instructions run
+pub struct Profiler {
+    instructions: u64,
+    addresses: Vec<u64>,
+    // Keyed by variant regardless of operands, with the variant's name
+    mix: HashMap<Discriminant<Instruction>, (String, u64)>,
+    // The targets of the calls in progress, innermost last, and when each began
+    stack: Vec<u16>,
+    call_starts: Vec<u64>,
+    subroutines: BTreeMap<u16, Subroutine>,
+    // Instructions run under each distinct call stack
+    stacks: HashMap<Vec<u16>, u64>,
+}
+
+impl Profiler {
+    pub fn new(ram_size: usize) -> Self {
+        Profiler {
+            instructions: 0,
+            addresses: vec![0; ram_size],
+            mix: HashMap::new(),
+            stack: Vec::new(),
+            call_starts: Vec::new(),
+            subroutines: BTreeMap::new(),
+            stacks: HashMap::new(),
+        }
+    }
+
+    // Forgets the calls in progress, for when the program starts over
+    pub fn restart(&mut self) {
+        self.stack.clear();
+        self.call_starts.clear();
+    }
+
+    // Counts the instruction at `pc` against the current call stack, then
+    // follows it into or out of a subroutine
+    pub fn record(&mut self, pc: usize, instruction: &Instruction) {
+        self.instructions += 1;
+        if let Some(count) = self.addresses.get_mut(pc) {
+            *count += 1;
+        }
+        self.mix.entry(mem::discriminant(instruction)).or_insert_with(|| {
+            let name = format!("{:?}", instruction);
+            (name.split('(').next().unwrap_or_default().to_string(), 0)
+        }).1 += 1;
+        match self.stacks.get_mut(&self.stack[..]) {
+            Some(count) => *count += 1,
+            None => {
+                self.stacks.insert(self.stack.clone(), 1);
+            },
+        }
+
+        match instruction {
+            Instruction::CallSubroutine(addr) => {
+                self.subroutines.entry(*addr).or_default().calls += 1;
+                self.stack.push(*addr);
+                self.call_starts.push(self.instructions);
+            },
+            Instruction::ReturnFromSubroutine => {
+                if let (Some(addr), Some(start)) = (self.stack.pop(), self.call_starts.pop()) {
+                    self.subroutines.entry(addr).or_default().instructions += self.instructions - start;
+                }
+            },
+            _ => {},
+        }
+    }
+
+    // Writes the report to `path` and the call stacks next to it with a
+    // .folded extension, in the format flamegraph tools read
+    pub fn write(&self, path: &Path, ram: &[u8], coverage: Option<&[u8]>, platform: Platform) -> io::Result<()> {
+        let mut report = File::create(path)?;
+        let total = self.instructions.max(1) as f64;
+        let percent = |count: u64| 100.0 * count as f64 / total;
+        writeln!(report, "{} instructions", self.instructions)?;
+
+        writeln!(report, "\nHot addresses")?;
+        let mut addresses: Vec<(usize, u64)> = self.addresses.iter().cloned().enumerate()
+            .filter(|&(_, count)| count > 0)
+            .collect();
+        addresses.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        for &(addr, count) in addresses.iter().take(HOT_ADDRESSES) {
+            let (text, _) = disassemble(ram, coverage, platform, addr);
+            writeln!(report, "  {:04X}  {:>12}  {:>6.2}%  {}", addr, count, percent(count), text)?;
+        }
+
+        writeln!(report, "\nInstruction mix")?;
+        let mut mix: Vec<&(String, u64)> = self.mix.values().collect();
+        mix.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        for (name, count) in mix {
+            writeln!(report, "  {:<20}  {:>12}  {:>6.2}%", name, count, percent(*count))?;
+        }
+
+        // Calls still in progress count up to now, though they never returned
+        let mut subroutines = self.subroutines.clone();
+        for (addr, start) in self.stack.iter().zip(self.call_starts.iter()) {
+            subroutines.entry(*addr).or_default().instructions += self.instructions - start;
+        }
+        writeln!(report, "\nSubroutines (instructions include nested calls)")?;
+        writeln!(report, "  {:<4}  {:>10}  {:>12}  {:>10}  {:>7}", "Addr", "Calls", "Instructions", "Per call", "Share")?;
+        let mut subroutines: Vec<(u16, Subroutine)> = subroutines.into_iter().collect();
+        subroutines.sort_by(|a, b| b.1.instructions.cmp(&a.1.instructions).then(a.0.cmp(&b.0)));
+        for (addr, sub) in subroutines {
+            let per_call = sub.instructions as f64 / sub.calls.max(1) as f64;
+            writeln!(report, "  {:04X}  {:>10}  {:>12}  {:>10.1}  {:>6.2}%",
+                addr, sub.calls, sub.instructions, per_call, percent(sub.instructions))?;
+        }
+
+        let mut folded = File::create(path.with_extension("folded"))?;
+        let mut stacks: Vec<(String, u64)> = self.stacks.iter().map(|(stack, count)| {
+            let mut frames = vec!["main".to_string()];
+            frames.extend(stack.iter().map(|addr| format!("sub_{:04X}", addr)));
+            (frames.join(";"), *count)
+        }).collect();
+        stacks.sort();
+        for (stack, count) in stacks {
+            writeln!(folded, "{} {}", stack, count)?;
+        }
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::interpreter;
+    use super::*;
+    use std::fs;
+    use std::process;
+
+    // Calls 0206, which calls 020C, then calls 0210, which never returns
+    const PROGRAM: [u16; 9] = [
+        0x2206, 0x2210, 0x1204,
+        0x6001, 0x220C, 0x00EE,
+        0x7001, 0x00EE,
+        0x1210,
+    ];
+
+    #[test]
+    fn reports_addresses_mix_and_subroutines() {
+        let mut interpreter = interpreter(&PROGRAM, Platform::Chip8);
+        interpreter.enable_profiler();
+        assert_eq!(interpreter.run(10), 10);
+        let path = std::env::temp_dir().join(format!("chip8rs-profile-{}.txt", process::id()));
+        interpreter.write_profile(&path).unwrap();
+        let report = fs::read_to_string(&path).unwrap();
+        let folded = fs::read_to_string(path.with_extension("folded")).unwrap();
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(path.with_extension("folded")).unwrap();
+        // Columns are compared with single spaces between them
+        let lines: Vec<String> = report.lines()
+            .map(|line| line.split_whitespace().collect::<Vec<&str>>().join(" "))
+            .collect();
+        let has = |line: &str| lines.iter().any(|found| found == line);
+
+        assert_eq!(lines[0], "10 instructions");
+        assert!(has("0210 3 30.00% JP 210"));
+        assert!(has("0200 1 10.00% CALL 206"));
+        assert!(has("020C 1 10.00% ADD V0, 01"));
+        assert!(has("020E 1 10.00% RET"));
+        assert!(!lines.iter().any(|line| line.starts_with("0204")));
+
+        assert!(has("CallSubroutine 3 30.00%"));
+        assert!(has("JumpToLoc 3 30.00%"));
+        assert!(has("ReturnFromSubroutine 2 20.00%"));
+        assert!(has("AddReg 1 10.00%"));
+        assert!(has("SetReg 1 10.00%"));
+
+        // 0206 runs its own two instructions, the return and all of 020C's;
+        // 0210 never returned but counts up to the end
+        assert!(has("0206 1 5 5.0 50.00%"));
+        assert!(has("020C 1 2 2.0 20.00%"));
+        assert!(has("0210 1 3 3.0 30.00%"));
+
+        assert_eq!(folded, "main 2\nmain;sub_0206 3\nmain;sub_0206;sub_020C 2\nmain;sub_0210 3\n");
+    }
+}
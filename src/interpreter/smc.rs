@@ -0,0 +1,154 @@
+use std::collections::HashSet;
+use std::mem;
+
+// A write that touched the program's own instructions
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct SmcEvent {
+    // The PC of the instruction that did the write
+    pub writer: u16,
+    pub addr: u16,
+    // Where the byte was run from after being written, if it was written first
+    pub fetched_at: Option<u16>,
+}
+
+// Watches for writes to addresses that have been run as instructions, and
+// for instructions run from addresses that have been written
+pub struct SmcMonitor {
+    executed: Vec<bool>,
+    // The writer of each byte written since it was last run
+    written_by: Vec<Option<u16>>,
+    // Each writer is only reported once per instruction it changes, since
+    // intentional self-modifying code does the same thing every frame
+    reported: HashSet<(u16, u16)>,
+    events: Vec<SmcEvent>,
+}
+
+impl SmcMonitor {
+    pub fn new(ram_size: usize) -> Self {
+        SmcMonitor {
+            executed: vec![false; ram_size],
+            written_by: vec![None; ram_size],
+            reported: HashSet::new(),
+            events: Vec::new(),
+        }
+    }
+
+    pub fn write(&mut self, pc: u16, addr: u16) {
+        if self.executed[addr as usize] {
+            self.report(SmcEvent { writer: pc, addr, fetched_at: None });
+        } else {
+            self.written_by[addr as usize] = Some(pc);
+        }
+    }
+
+    // Notes that the byte at `addr` is being run as part of the instruction at `pc`
+    pub fn execute(&mut self, pc: u16, addr: u16) {
+        self.executed[addr as usize] = true;
+        if let Some(writer) = self.written_by[addr as usize].take() {
+            self.report(SmcEvent { writer, addr, fetched_at: Some(pc) });
+        }
+    }
+
+    fn report(&mut self, event: SmcEvent) {
+        if self.reported.insert((event.writer, event.fetched_at.unwrap_or(event.addr))) {
+            self.events.push(event);
+        }
+    }
+
+    pub fn take_events(&mut self) -> Vec<SmcEvent> {
+        return mem::take(&mut self.events);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::interpreter;
+    use super::super::Command;
+    use super::*;
+    use crate::platform::Platform;
+    use std::sync::atomic::Ordering;
+
+    #[test]
+    fn reports_writes_over_instructions_that_already_ran() {
+        let mut smc = SmcMonitor::new(0x1000);
+        smc.execute(0x200, 0x200);
+        smc.execute(0x200, 0x201);
+        smc.write(0x210, 0x300);
+        assert_eq!(smc.take_events(), vec![]);
+        smc.write(0x210, 0x201);
+        assert_eq!(smc.take_events(), vec![SmcEvent { writer: 0x210, addr: 0x201, fetched_at: None }]);
+    }
+
+    #[test]
+    fn reports_running_bytes_that_were_written() {
+        let mut smc = SmcMonitor::new(0x1000);
+        smc.write(0x204, 0x300);
+        smc.write(0x204, 0x301);
+        smc.execute(0x300, 0x300);
+        smc.execute(0x300, 0x301);
+        // Both bytes of the instruction count as one change
+        assert_eq!(smc.take_events(), vec![SmcEvent { writer: 0x204, addr: 0x300, fetched_at: Some(0x300) }]);
+        // Running it again without another write is fine
+        smc.execute(0x300, 0x300);
+        assert_eq!(smc.take_events(), vec![]);
+    }
+
+    #[test]
+    fn reports_each_writer_and_instruction_once() {
+        let mut smc = SmcMonitor::new(0x1000);
+        smc.execute(0x200, 0x200);
+        for _ in 0..3 {
+            smc.write(0x210, 0x200);
+            smc.execute(0x200, 0x200);
+        }
+        smc.write(0x212, 0x200);
+        let events = smc.take_events();
+        assert_eq!(events, vec![
+            SmcEvent { writer: 0x210, addr: 0x200, fetched_at: None },
+            SmcEvent { writer: 0x212, addr: 0x200, fetched_at: None },
+        ]);
+    }
+
+    fn break_reason(interpreter: &super::super::Interpreter) -> Option<String> {
+        return interpreter.stats.break_reason.lock().unwrap().clone();
+    }
+
+    #[test]
+    fn breaks_when_the_program_writes_over_itself() {
+        // I = 0202, then FX55 writes the A2 back over the I = 0202 it ran,
+        // and jumps back to do it again
+        let mut interpreter = interpreter(&[0x60A2, 0xA202, 0xF055, 0x1202], Platform::Chip8);
+        interpreter.enable_smc_monitor(true);
+        assert_eq!(interpreter.run(100), 3);
+        assert!(interpreter.paused);
+        assert!(interpreter.stats.paused.load(Ordering::Relaxed));
+        assert!(interpreter.stats.break_requested.load(Ordering::Relaxed));
+        assert_eq!(break_reason(&interpreter).as_deref(), Some("0204 LD [I], V0 wrote over the instruction byte at 0202"));
+
+        // The same write doesn't break again
+        interpreter.run_command(Command::TogglePause);
+        assert_eq!(break_reason(&interpreter), None);
+        assert_eq!(interpreter.run(100), 100);
+        assert!(!interpreter.paused);
+    }
+
+    #[test]
+    fn breaks_when_the_program_runs_what_it_wrote() {
+        // Writes 1208 to 0208 with FX55 and runs it
+        let mut interpreter = interpreter(&[0xA208, 0x6012, 0x6108, 0xF155, 0x0000], Platform::Chip8);
+        interpreter.enable_smc_monitor(true);
+        assert_eq!(interpreter.run(100), 5);
+        assert!(interpreter.paused);
+        assert_eq!(break_reason(&interpreter).as_deref(), Some("0208 runs an instruction written by 0206 LD [I], V1"));
+        assert_eq!(interpreter.snapshot().pc, 0x208);
+    }
+
+    #[test]
+    fn only_logs_without_break_mode() {
+        let mut interpreter = interpreter(&[0xA208, 0x6012, 0x6108, 0xF155, 0x0000], Platform::Chip8);
+        interpreter.enable_smc_monitor(false);
+        assert_eq!(interpreter.run(100), 100);
+        assert!(!interpreter.paused);
+        assert_eq!(break_reason(&interpreter), None);
+    }
+}
@@ -0,0 +1,172 @@
+use crate::config::{Config, Value};
+
+// Host keys for keypad 0x0 to 0xF, laid out as 1234/QWER/ASDF/ZXCV
+const DEFAULT_LAYOUT: [&str; 16] = [
+    "x", "1", "2", "3", "q", "w", "e", "a", "s", "d", "z", "c", "4", "r", "f", "v",
+];
+
+// Which host keys press each of the 16 keypad keys. Read from the [keymap]
+// table, then any layout the ROM database suggests, then from
+// [roms.<rom name>.keymap], where each entry replaces the bindings for one
+// keypad key:
+//
+//     [roms.PONG.keymap]
+//     1 = ["1", "w"]
+//     4 = ["q", "s"]
+#[derive(Clone, PartialEq, Debug)]
+pub struct Keymap {
+    bindings: Vec<Vec<String>>,
+}
+
+impl Keymap {
+    pub fn new() -> Self {
+        Keymap {
+            bindings: DEFAULT_LAYOUT.iter().map(|key| vec![key.to_string()]).collect(),
+        }
+    }
+
+    pub fn load(config: &Config, rom_name: &str, suggested: Option<&Value>) -> Result<Self, String> {
+        let mut keymap = Keymap::new();
+        if let Some(table) = config.get("keymap") {
+            keymap.apply(table)?;
+        }
+        if let Some(table) = suggested {
+            keymap.apply(table)?;
+        }
+        let rom_table = config.get("roms")
+            .and_then(|roms| roms.as_table())
+            .and_then(|roms| roms.get(rom_name))
+            .and_then(|rom| rom.get("keymap"));
+        if let Some(table) = rom_table {
+            keymap.apply(table)?;
+        }
+        return Ok(keymap);
+    }
+
+    // Replaces the bindings of every keypad key named in `table`
+    pub fn apply(&mut self, table: &Value) -> Result<(), String> {
+        let table = table.as_table().ok_or("keymap must be a table")?;
+        for (key, value) in table.iter() {
+            let key_id = match u8::from_str_radix(key, 16) {
+                Ok(key_id) if key_id <= 0xF => key_id,
+                _ => return Err(format!("keymap key {} is not a hex digit", key)),
+            };
+            let host_keys = match value {
+                Value::String(name) => vec![normalize(name)],
+                Value::Array(names) => names.iter().map(|name| {
+                    name.as_str().map(normalize).ok_or_else(|| format!("keymap {} has a non-string key", key))
+                }).collect::<Result<Vec<String>, String>>()?,
+                _ => return Err(format!("keymap {} must be a key name or a list of them", key)),
+            };
+            self.bindings[key_id as usize] = host_keys;
+        }
+        return Ok(());
+    }
+
+    pub fn host_keys(&self, key_id: u8) -> &[String] {
+        &self.bindings[key_id as usize]
+    }
+
+    // Every keypad key the host key is bound to
+    pub fn lookup(&self, host_key: &str) -> Vec<u8> {
+        let host_key = normalize(host_key);
+        (0..16).filter(|key_id| self.bindings[*key_id as usize].contains(&host_key)).collect()
+    }
+}
+
+// Key names are compared case-insensitively, with the digit keys of the
+// various backends ("Key1", "D1") all called "1"
+pub fn normalize(name: &str) -> String {
+    let name = name.trim().to_lowercase();
+    for prefix in ["key", "d"].iter() {
+        if let Some(rest) = name.strip_prefix(prefix) {
+            if rest.len() == 1 && rest.chars().all(|c| c.is_ascii_digit()) {
+                return rest.to_string();
+            }
+        }
+    }
+    return name;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+        [keymap]
+        1 = "up"
+        2 = ["Space", "KEY2"]
+
+        [roms.PONG.keymap]
+        1 = ["w", "Up"]
+    "#;
+
+    fn suggestion(text: &str) -> Value {
+        return Config::parse(text).unwrap().root().clone();
+    }
+
+    #[test]
+    fn defaults_to_the_1234_qwer_layout() {
+        let keymap = Keymap::load(&Config::empty(), "PONG", None).unwrap();
+        assert_eq!(keymap, Keymap::new());
+        assert_eq!(keymap.lookup("x"), vec![0x0]);
+        assert_eq!(keymap.lookup("4"), vec![0xC]);
+        assert_eq!(keymap.lookup("v"), vec![0xF]);
+        assert_eq!(keymap.lookup("up"), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn config_then_suggestion_then_rom_table() {
+        let config = Config::parse(CONFIG).unwrap();
+        let suggested = suggestion("1 = 'j'\n3 = 'k'\n");
+
+        // [keymap] replaces only the keys it names
+        let keymap = Keymap::load(&config, "TETRIS", None).unwrap();
+        assert_eq!(keymap.host_keys(0x1), ["up"]);
+        assert_eq!(keymap.host_keys(0x2), ["space", "2"]);
+        assert_eq!(keymap.host_keys(0x3), ["3"]);
+
+        // The database's suggestion goes over [keymap]
+        let keymap = Keymap::load(&config, "TETRIS", Some(&suggested)).unwrap();
+        assert_eq!(keymap.host_keys(0x1), ["j"]);
+        assert_eq!(keymap.host_keys(0x2), ["space", "2"]);
+        assert_eq!(keymap.host_keys(0x3), ["k"]);
+
+        // And the ROM's own table goes over both
+        let keymap = Keymap::load(&config, "PONG", Some(&suggested)).unwrap();
+        assert_eq!(keymap.host_keys(0x1), ["w", "up"]);
+        assert_eq!(keymap.host_keys(0x3), ["k"]);
+        assert_eq!(keymap.lookup("UP"), vec![0x1]);
+    }
+
+    #[test]
+    fn one_host_key_can_press_several_keypad_keys() {
+        let config = Config::parse("[keymap]\n4 = ['q', 'space']\n6 = ['e', 'space']\n").unwrap();
+        let keymap = Keymap::load(&config, "PONG", None).unwrap();
+        assert_eq!(keymap.lookup("Space"), vec![0x4, 0x6]);
+    }
+
+    #[test]
+    fn rejects_broken_keymaps() {
+        for text in ["[keymap]\n10 = 'a'\n", "[keymap]\ng = 'a'\n", "[keymap]\n1 = 5\n", "[keymap]\n1 = ['a', 5]\n", "keymap = 'a'\n"].iter() {
+            let config = Config::parse(text).unwrap();
+            assert!(Keymap::load(&config, "PONG", None).is_err(), "{}", text);
+        }
+        let config = Config::parse("[roms.PONG]\nkeymap = 'a'\n").unwrap();
+        assert!(Keymap::load(&config, "PONG", None).is_err());
+        assert!(Keymap::load(&config, "TETRIS", None).is_ok());
+        assert!(Keymap::load(&Config::empty(), "PONG", Some(&suggestion("1 = 1\n"))).is_err());
+    }
+
+    #[test]
+    fn normalizes_key_names() {
+        assert_eq!(normalize(" Space "), "space");
+        assert_eq!(normalize("KEY1"), "1");
+        assert_eq!(normalize("Key1"), "1");
+        assert_eq!(normalize("D7"), "7");
+        assert_eq!(normalize("d"), "d");
+        assert_eq!(normalize("Delete"), "delete");
+        assert_eq!(normalize("Key12"), "key12");
+        assert_eq!(normalize("keyA"), "keya");
+    }
+}
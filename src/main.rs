@@ -1,42 +1,271 @@
+#![allow(clippy::needless_return)]
+
+mod audio;
+mod bench;
+mod cheat;
+mod config;
+mod display;
 mod interpreter;
 mod game;
+mod gamepad;
+mod keymap;
+mod palette;
+mod platform;
+mod rom;
+mod romdb;
+mod tui;
 
-use interpreter::Interpreter;
+use audio::{Audio, AudioSettings, AudioSink, NullSink, WavSink, Waveform};
+use config::Config;
+use interpreter::{Command, Interpreter};
 use game::*;
+use gamepad::{GamepadInput, GamepadMapping};
+use keymap::Keymap;
+use palette::Palette;
+use platform::{Overrides, Platform};
+use romdb::RomDatabase;
+use std::env;
+use std::path::Path;
+use std::str::FromStr;
 use std::io;
-use std::io::prelude::*;
-use std::fs::File;
 use std::sync::Mutex;
 use std::sync::Arc;
 use std::thread;
 
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+fn parse_arg<T: FromStr>(flag: &str, value: Option<String>) -> io::Result<T> {
+    let value = value.unwrap_or_default();
+    value.parse().map_err(|_| invalid_input(format!("Invalid value {:?} for {}", value, flag)))
+}
+
 fn main() -> io::Result<()> {
-    let mut f = File::open("roms/INVADERS")?;
-    let mut read_buffer = Vec::new();
-    f.read_to_end(&mut read_buffer)?;
-    let mut first_byte = true;
-    let mut half_word = 0x0000;
-    let mut instructions = Vec::new();
-    for byte in read_buffer {
-        if first_byte {
-            half_word |= (byte as u16) << 8;
-            first_byte = false;
-        } else {
-            half_word |= byte as u16;
-            instructions.push(half_word);
-            half_word = 0x0000;
-            first_byte = true;
+    let mut rom_path = "roms/INVADERS".to_string();
+    let mut overrides = Overrides::default();
+    let mut audio_settings = AudioSettings::new();
+    let mut wav_path = None;
+    let mut mute = false;
+    let mut headless = false;
+    let mut use_tui = false;
+    let mut bench_seconds = None;
+    let mut config_path = config::DEFAULT_PATH.to_string();
+    let mut palette_name = None;
+    let mut persistence_name = None;
+    let mut gamepad_path = None;
+    let mut profile_path: Option<String> = None;
+    let mut coverage_path: Option<String> = None;
+    let mut smc_break = None;
+    let mut decode_cache = true;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--platform" => {
+                let name = args.next().unwrap_or_default();
+                overrides.platform = Some(Platform::from_name(&name).ok_or_else(|| {
+                    invalid_input(format!("Unknown platform {}", name))
+                })?);
+            },
+            "--beep-freq" => audio_settings.frequency = parse_arg(&arg, args.next())?,
+            "--volume" => audio_settings.volume = parse_arg(&arg, args.next())?,
+            "--waveform" => {
+                let name = args.next().unwrap_or_default();
+                audio_settings.waveform = Waveform::from_name(&name).ok_or_else(|| {
+                    invalid_input(format!("Unknown waveform {}", name))
+                })?;
+            },
+            "--wav" => wav_path = args.next(),
+            "--mute" => mute = true,
+            "--headless" => headless = true,
+            "--tui" => use_tui = true,
+            "--bench" => bench_seconds = Some(parse_arg(&arg, args.next())?),
+            "--bench-handoff" => {
+                bench::handoff(parse_arg(&arg, args.next())?);
+                return Ok(());
+            },
+            "--config" => config_path = parse_arg(&arg, args.next())?,
+            "--palette" => palette_name = args.next(),
+            "--persistence" => persistence_name = args.next(),
+            "--gamepad" => gamepad_path = args.next(),
+            "--profile" => profile_path = args.next(),
+            "--coverage" => coverage_path = args.next(),
+            "--no-decode-cache" => decode_cache = false,
+            "--smc" => {
+                let mode = args.next().unwrap_or_default();
+                smc_break = match mode.as_str() {
+                    "log" => Some(false),
+                    "break" => Some(true),
+                    _ => return Err(invalid_input(format!("Unknown self-modifying code mode {}", mode))),
+                };
+            },
+            "--speed" => overrides.speed = Some(parse_arg(&arg, args.next())?),
+            "--wrap" => overrides.wrap_sprites = Some(true),
+            "--clip" => overrides.wrap_sprites = Some(false),
+            _ => rom_path = arg,
+        }
+    }
+
+    let config = Config::load(&config_path)?;
+    let mut palettes = Palette::load_all(&config).map_err(invalid_input)?;
+    let palette_chosen = palette_name.is_some();
+    let palette_name = palette_name.or_else(|| {
+        config.get("palette.default").and_then(|v| v.as_str()).map(|s| s.to_string())
+    });
+    let mut palette = match palette_name {
+        Some(name) => palettes.iter().position(|p| p.name == name).ok_or_else(|| {
+            invalid_input(format!("Unknown palette {}", name))
+        })?,
+        None => 0,
+    };
+
+    let persistence_name = persistence_name.or_else(|| {
+        config.get("display.persistence").and_then(|v| v.as_str()).map(|s| s.to_string())
+    });
+    let persistence = match persistence_name {
+        Some(name) => Persistence::from_name(&name).ok_or_else(|| {
+            invalid_input(format!("Unknown persistence filter {}", name))
+        })?,
+        None => Persistence::Off,
+    };
+
+    let rom = rom::load(Path::new(&rom_path)).map_err(|e| {
+        io::Error::new(e.kind(), format!("Couldn't load {}: {}", rom_path, e))
+    })?;
+    // A cartridge's colors stand in for the configured palette but not one asked for
+    if let Some(colors) = rom.colors {
+        let idx = Palette::insert(&mut palettes, Palette::cartridge(colors));
+        if !palette_chosen {
+            palette = idx;
         }
     }
+    let database = RomDatabase::load(&config)?;
+    let entry = database.lookup(&rom.hash);
+    if let Some(name) = entry.and_then(|entry| entry.display_name()) {
+        println!("{}", name);
+    }
+    if let Some(description) = entry.and_then(|entry| entry.description.as_ref()) {
+        println!("{}", description);
+    }
+    let settings = romdb::resolve(&rom.overrides, entry, &overrides);
+
+    let rom_name = Path::new(&rom_path).file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let suggested_keymap = entry.and_then(|entry| entry.keymap.as_ref());
+    let keymap = Keymap::load(&config, &rom_name, suggested_keymap).map_err(invalid_input)?;
+    let gamepad_mapping = GamepadMapping::load(&config, &rom_name).map_err(invalid_input)?;
+    let gamepad_path = gamepad_path.or_else(|| {
+        config.get("gamepad.device").and_then(|v| v.as_str()).map(|s| s.to_string())
+    });
+
+    let cheat_path = cheat::path_for(&cheat::dir(&config), &rom.hash);
+    let cheats = cheat::load(&cheat_path)?;
     let display_state = Arc::new(Mutex::new(GameState::new()));
     let clone = display_state.clone();
-    let mut interpreter = Interpreter::new(instructions, clone);
-    thread::spawn(|| {
-        let mut display = Game::new("Test title".to_string(), display_state);
-        display.start();
-    });
+    let (frame_writer, frame_reader) = display::frame_channel(settings.platform);
+    let mut interpreter = Interpreter::new(rom.bytes, clone, frame_writer, settings).map_err(|e| {
+        invalid_input(format!("Couldn't load {}: {}", rom_path, e))
+    })?;
+    interpreter.set_cheats(cheats.clone());
+    if !decode_cache {
+        interpreter.disable_decode_cache();
+    }
+    if profile_path.is_some() {
+        interpreter.enable_profiler();
+    }
+    if coverage_path.is_some() {
+        interpreter.enable_coverage();
+    }
+    if let Some(smc_break) = smc_break {
+        // Only the window has a debugger to break into
+        interpreter.enable_smc_monitor(smc_break && !headless && !use_tui && bench_seconds.is_none());
+    }
+
+    if let Some(seconds) = bench_seconds {
+        bench::run(&mut interpreter, frame_reader, seconds);
+        return write_reports(&interpreter, profile_path, coverage_path);
+    }
+
+    let sink: Box<dyn AudioSink> = if let Some(path) = wav_path {
+        Box::new(WavSink::create(&path, audio_settings.sample_rate)?)
+    } else if mute || headless || use_tui {
+        Box::new(NullSink)
+    } else {
+        match audio::open_output(&mut audio_settings) {
+            Ok(sink) => sink,
+            Err(e) => {
+                eprintln!("No audio output available: {}", e);
+                Box::new(NullSink)
+            }
+        }
+    };
+    interpreter.set_audio(Audio::new(audio_settings, sink));
+
+    let mut gamepad = None;
+    if let Some(path) = gamepad_path {
+        let backend = gamepad::open(&path).map_err(|e| {
+            io::Error::new(e.kind(), format!("Couldn't open gamepad {}: {}", path, e))
+        })?;
+        gamepad = Some(GamepadInput::new(gamepad_mapping).start(backend, display_state.clone()));
+    }
+
+    let mut tui_thread = None;
+    if use_tui {
+        let palette = palettes[palette].clone();
+        let mut tui = tui::Tui::new(display_state, frame_reader, interpreter.stats(), palette, keymap);
+        let controls = interpreter.controls();
+        // The interpreter keeps the main thread, so quitting from the terminal stops it
+        tui_thread = Some(thread::spawn(move || {
+            let result = tui.start();
+            drop(tui);
+            if let Err(e) = result {
+                eprintln!("Terminal error: {}", e);
+            }
+            let _ = controls.send(Command::Quit);
+        }));
+    } else if !headless {
+        let controls = interpreter.controls();
+        let stats = interpreter.stats();
+        let debug_snapshot = interpreter.debug_snapshot();
+        thread::spawn(move || {
+            let mut display = Game::new(rom_name, display_state, frame_reader, stats);
+            display.set_palettes(palettes, palette);
+            display.set_persistence(persistence);
+            display.set_keymap(keymap);
+            if let Some(gamepad) = gamepad {
+                display.set_gamepad(gamepad);
+            }
+            display.set_config(config);
+            display.set_debug_snapshot(debug_snapshot);
+            display.set_cheats(cheats, cheat_path);
+            display.set_rom_database(database, overrides);
+            display.set_controls(controls, rom_path.into());
+            display.start();
+        });
+    }
     // interpreter.print_program();
     interpreter.interpret();
+    // Leave the final screen up until the user quits
+    if let Some(tui_thread) = tui_thread {
+        let _ = tui_thread.join();
+    }
 
-    Ok(())
+    return write_reports(&interpreter, profile_path, coverage_path);
+}
+
+fn write_reports(interpreter: &Interpreter, profile_path: Option<String>, coverage_path: Option<String>) -> io::Result<()> {
+    if let Some(path) = profile_path {
+        interpreter.write_profile(Path::new(&path)).map_err(|e| {
+            io::Error::new(e.kind(), format!("Couldn't write profile {}: {}", path, e))
+        })?;
+        println!("Profile written to {}", path);
+    }
+    if let Some(path) = coverage_path {
+        interpreter.write_coverage(Path::new(&path)).map_err(|e| {
+            io::Error::new(e.kind(), format!("Couldn't write coverage {}: {}", path, e))
+        })?;
+        println!("Coverage written to {}", path);
+    }
+    return Ok(());
 }
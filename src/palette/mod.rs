@@ -0,0 +1,153 @@
+use crate::config::Config;
+
+// Four RGBA colors indexed by a pixel's plane bits, so XO-CHIP's second plane
+// gets its own colors while plain CHIP-8 only ever uses the first two
+#[derive(Clone, PartialEq, Debug)]
+pub struct Palette {
+    pub name: String,
+    pub colors: [[u8; 4]; 4],
+}
+
+impl Palette {
+    fn builtin(name: &str, colors: [u32; 4]) -> Self {
+        let mut palette = Palette {
+            name: name.to_string(),
+            colors: [[0x00; 4]; 4],
+        };
+        for (color, rgb) in palette.colors.iter_mut().zip(colors.iter()) {
+            *color = [(rgb >> 16) as u8, (rgb >> 8) as u8, *rgb as u8, 0xFF];
+        }
+        return palette;
+    }
+
+    pub fn builtins() -> Vec<Palette> {
+        vec![
+            Palette::builtin("mono", [0x000000, 0xFFFFFF, 0xAAAAAA, 0x555555]),
+            Palette::builtin("green", [0x0A1A0A, 0x33FF66, 0x1F9940, 0x145926]),
+            Palette::builtin("amber", [0x1A0F00, 0xFFB000, 0xB36B00, 0x663D00]),
+            Palette::builtin("lcd", [0x9BBC0F, 0x0F380F, 0x306230, 0x8BAC0F]),
+            Palette::builtin("octo", [0x996600, 0xFFCC00, 0xFF6600, 0x662200]),
+        ]
+    }
+
+    // Parses "#RRGGBB" or "RRGGBB"
+    pub fn parse_hex(text: &str) -> Option<[u8; 4]> {
+        let hex = text.strip_prefix('#').unwrap_or(text);
+        if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        let rgb = u32::from_str_radix(hex, 16).ok()?;
+        return Some([(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8, 0xFF]);
+    }
+
+    // The colors saved in an Octo cartridge
+    pub fn cartridge(colors: [[u8; 4]; 4]) -> Self {
+        return Palette {
+            name: "cartridge".to_string(),
+            colors,
+        };
+    }
+
+    // Adds `palette` to the list in place of any with the same name, returning its index
+    pub fn insert(palettes: &mut Vec<Palette>, palette: Palette) -> usize {
+        match palettes.iter().position(|p| p.name == palette.name) {
+            Some(idx) => {
+                palettes[idx] = palette;
+                return idx;
+            },
+            None => {
+                palettes.push(palette);
+                return palettes.len() - 1;
+            },
+        }
+    }
+
+    // The built-in palettes followed by any [palettes.<name>] tables, whose
+    // `colors` list two to four hex colors. A table named after a built-in
+    // palette replaces it.
+    pub fn load_all(config: &Config) -> Result<Vec<Palette>, String> {
+        let mut palettes = Palette::builtins();
+        let custom = match config.get("palettes").and_then(|v| v.as_table()) {
+            Some(custom) => custom,
+            None => return Ok(palettes),
+        };
+        for (name, table) in custom.iter() {
+            if let Some(key) = table.as_table().and_then(|t| t.keys().find(|key| *key != "colors")) {
+                return Err(format!("palette {} has an unknown setting {}", name, key));
+            }
+            let colors = table.get("colors").and_then(|v| v.as_array())
+                .ok_or_else(|| format!("palette {} has no colors", name))?;
+            if colors.len() < 2 || colors.len() > 4 {
+                return Err(format!("palette {} needs 2 to 4 colors", name));
+            }
+            let mut palette = Palette::builtins()[0].clone();
+            palette.name = name.clone();
+            for (idx, color) in colors.iter().enumerate() {
+                palette.colors[idx] = color.as_str().and_then(Palette::parse_hex)
+                    .ok_or_else(|| format!("palette {} has an invalid color {:?}", name, color))?;
+            }
+            match palettes.iter_mut().find(|p| p.name == *name) {
+                Some(existing) => *existing = palette,
+                None => palettes.push(palette),
+            }
+        }
+        return Ok(palettes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(text: &str) -> Result<Vec<Palette>, String> {
+        return Palette::load_all(&Config::parse(text).unwrap());
+    }
+
+    #[test]
+    fn parses_hex_colors() {
+        assert_eq!(Palette::parse_hex("#FFCC00"), Some([0xFF, 0xCC, 0x00, 0xFF]));
+        assert_eq!(Palette::parse_hex("0a1b2c"), Some([0x0A, 0x1B, 0x2C, 0xFF]));
+        for bad in ["", "#", "#FFF", "#FFCC000", "##FFCC0", "#FFCC0G", "+FFFFF", "#-FFFFF", "FF CC 0"].iter() {
+            assert_eq!(Palette::parse_hex(bad), None, "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn custom_palettes_follow_the_builtins() {
+        let palettes = load(r##"
+            [palettes.paper]
+            colors = ["#FFFFFF", "#000000"]
+            [palettes.neon]
+            colors = ["#000000", "#FF00FF", "#00FFFF", "#FFFFFF"]
+        "##).unwrap();
+        let builtins = Palette::builtins();
+        assert_eq!(&palettes[..builtins.len()], &builtins[..]);
+        let names: Vec<&str> = palettes[builtins.len()..].iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, vec!["neon", "paper"]);
+        let paper = palettes.iter().find(|p| p.name == "paper").unwrap();
+        assert_eq!(paper.colors[0], [0xFF, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(paper.colors[1], [0x00, 0x00, 0x00, 0xFF]);
+        // Unset planes keep mono's colors
+        assert_eq!(paper.colors[2..], builtins[0].colors[2..]);
+    }
+
+    #[test]
+    fn custom_palettes_replace_builtins_of_the_same_name() {
+        let palettes = load("[palettes.amber]\ncolors = ['#112233', '#445566']\n").unwrap();
+        assert_eq!(palettes.len(), Palette::builtins().len());
+        let amber = palettes.iter().find(|p| p.name == "amber").unwrap();
+        assert_eq!(amber.colors[0], [0x11, 0x22, 0x33, 0xFF]);
+        assert_eq!(amber.colors[1], [0x44, 0x55, 0x66, 0xFF]);
+    }
+
+    #[test]
+    fn rejects_broken_palettes() {
+        assert!(load("[palettes.a]\ncolors = ['#000000', '#GGGGGG']\n").unwrap_err().contains("invalid color"));
+        assert!(load("[palettes.a]\ncolors = ['#000000', 7]\n").unwrap_err().contains("invalid color"));
+        assert!(load("[palettes.a]\ncolors = ['#000000']\n").unwrap_err().contains("2 to 4"));
+        assert!(load("[palettes.a]\ncolors = ['#000000', '#000000', '#000000', '#000000', '#000000']\n").is_err());
+        assert!(load("[palettes.a]\ncolours = ['#000000', '#FFFFFF']\n").unwrap_err().contains("unknown setting colours"));
+        assert!(load("[palettes.a]\ncolors = ['#000000', '#FFFFFF']\nname = 'x'\n").unwrap_err().contains("unknown setting name"));
+        assert!(load("[palettes]\na = 1\n").unwrap_err().contains("no colors"));
+    }
+}
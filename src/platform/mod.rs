@@ -0,0 +1,153 @@
+// The machines a ROM can target, which decide the display size and start address
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Platform {
+    Chip8,
+    // The 64x64 HIRES CHIP-8 variant for the COSMAC VIP
+    Chip8Hires,
+    Chip10,
+    SuperChip,
+    XoChip,
+}
+
+// Groups of instructions that later interpreters added to CHIP-8
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Extension {
+    // 0230 clears the 64x64 screen
+    HiresClear,
+    // Scrolling, exit, hires mode, 16x16 sprites, the large font and RPL flags
+    SuperChip,
+    // Long I loads, bitplanes, register ranges, scrolling up and audio patterns
+    XoChip,
+}
+
+// Behaviours that differ between interpreters of the same platform
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Quirks {
+    // Sprite pixels past the screen edge wrap around instead of being clipped
+    pub wrap_sprites: bool,
+}
+
+// Where programs are loaded, above the space the original interpreter used
+pub const PROGRAM_ADDR: u16 = 0x200;
+
+// Instructions run per 60Hz frame when throttled, unless a ROM asks otherwise
+pub const DEFAULT_SPEED: u32 = 6;
+
+// Everything about how to run a program besides the program itself
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Settings {
+    pub platform: Platform,
+    pub quirks: Quirks,
+    // Instructions per frame
+    pub speed: u32,
+}
+
+impl Settings {
+    pub fn new(platform: Platform) -> Self {
+        Settings {
+            platform,
+            quirks: platform.default_quirks(),
+            speed: DEFAULT_SPEED,
+        }
+    }
+}
+
+// Settings picked for a ROM by the command line or the ROM database, where
+// None leaves the setting as it was
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Overrides {
+    pub platform: Option<Platform>,
+    pub wrap_sprites: Option<bool>,
+    pub speed: Option<u32>,
+}
+
+impl Overrides {
+    // Switching platform brings in its default quirks before any are overridden
+    pub fn apply(&self, settings: &mut Settings) {
+        if let Some(platform) = self.platform {
+            if platform != settings.platform {
+                settings.platform = platform;
+                settings.quirks = platform.default_quirks();
+            }
+        }
+        if let Some(wrap_sprites) = self.wrap_sprites {
+            settings.quirks.wrap_sprites = wrap_sprites;
+        }
+        if let Some(speed) = self.speed {
+            settings.speed = speed;
+        }
+    }
+}
+
+impl Platform {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "chip8" | "chip-8" => Some(Platform::Chip8),
+            "hires" | "chip8-hires" => Some(Platform::Chip8Hires),
+            "chip10" | "chip-10" => Some(Platform::Chip10),
+            "schip" | "superchip" => Some(Platform::SuperChip),
+            "xochip" | "xo-chip" => Some(Platform::XoChip),
+            _ => None,
+        }
+    }
+
+    // Display size in the default resolution
+    pub fn lores_size(&self) -> (usize, usize) {
+        match self {
+            Platform::Chip8 => (64, 32),
+            Platform::Chip8Hires => (64, 64),
+            Platform::Chip10 => (128, 64),
+            Platform::SuperChip => (64, 32),
+            Platform::XoChip => (64, 32),
+        }
+    }
+
+    // Display size after 00FF, which is the default size where there is no hires mode
+    pub fn hires_size(&self) -> (usize, usize) {
+        match self {
+            Platform::SuperChip | Platform::XoChip => (128, 64),
+            _ => self.lores_size(),
+        }
+    }
+
+    // Whether programs for this platform can use the extension's instructions
+    pub fn supports(&self, extension: Extension) -> bool {
+        match extension {
+            Extension::HiresClear => *self == Platform::Chip8Hires,
+            Extension::SuperChip => *self == Platform::SuperChip || *self == Platform::XoChip,
+            Extension::XoChip => *self == Platform::XoChip,
+        }
+    }
+
+    // Bytes of memory, which is 4K everywhere but XO-CHIP
+    pub fn memory_size(&self) -> usize {
+        match self {
+            Platform::XoChip => 0x10000,
+            _ => 0x1000,
+        }
+    }
+
+    // Programs load at 0x200, so they have to fit in the memory above that
+    pub fn check_program_size(&self, len: usize) -> Result<(), String> {
+        let room = self.memory_size() - PROGRAM_ADDR as usize;
+        if len > room {
+            return Err(format!("program is {} bytes but only {} fit in {:?} memory", len, room, self));
+        }
+        return Ok(());
+    }
+
+    pub fn default_quirks(&self) -> Quirks {
+        Quirks {
+            wrap_sprites: *self == Platform::XoChip,
+        }
+    }
+
+    // HIRES CHIP-8 programs begin with a 1260 jump into an interpreter patch,
+    // which we emulate natively by starting at the program proper
+    pub fn start_addr(&self, program: &[u8]) -> u16 {
+        match self {
+            Platform::Chip8Hires if program.starts_with(&[0x12, 0x60]) => 0x2C0,
+            _ => PROGRAM_ADDR,
+        }
+    }
+}
@@ -0,0 +1,165 @@
+use crate::config::Value;
+use crate::platform::{Overrides, Platform};
+use std::collections::BTreeMap;
+
+// What an Octo cartridge carries: the Octo source of the program and the
+// emulator options it was saved with
+pub struct Cartridge {
+    pub program: String,
+    pub options: BTreeMap<String, Value>,
+}
+
+// Octo hides its payload in the low nibble of each palette index, two pixels
+// to a byte across every frame, as a 32-bit big-endian length and that many
+// bytes of UTF-8 JSON
+pub fn parse(bytes: &[u8]) -> Result<Cartridge, String> {
+    let mut options = gif::DecodeOptions::new();
+    options.set_color_output(gif::ColorOutput::Indexed);
+    let mut decoder = options.read_info(bytes).map_err(|e| e.to_string())?;
+    let mut payload = Vec::new();
+    while let Some(frame) = decoder.read_next_frame().map_err(|e| e.to_string())? {
+        for pair in frame.buffer.chunks_exact(2) {
+            payload.push((pair[0] & 0x0F) << 4 | (pair[1] & 0x0F));
+        }
+    }
+    if payload.len() < 4 {
+        return Err("no cartridge data in image".to_string());
+    }
+    let len = u32::from_be_bytes([payload[0], payload[1], payload[2], payload[3]]) as usize;
+    if len > payload.len() - 4 {
+        return Err("cartridge data is truncated".to_string());
+    }
+    let json: serde_json::Value = serde_json::from_slice(&payload[4..4 + len])
+        .map_err(|e| format!("invalid cartridge data: {}", e))?;
+    let mut root = match from_json(json) {
+        Value::Table(root) => root,
+        _ => return Err("cartridge data is not an object".to_string()),
+    };
+    let program = match root.remove("program") {
+        Some(Value::String(program)) => program,
+        _ => return Err("cartridge has no program".to_string()),
+    };
+    let options = match root.remove("options") {
+        Some(Value::Table(options)) => options,
+        _ => BTreeMap::new(),
+    };
+    return Ok(Cartridge { program, options });
+}
+
+impl Cartridge {
+    // Octo picks a platform by the memory it allows a program, then lets the
+    // clip quirk and tickrate be set on their own
+    pub fn overrides(&self) -> Overrides {
+        let option = |name: &str| self.options.get(name);
+        let platform = option("maxSize").and_then(|v| v.as_integer()).map(|size| {
+            match size {
+                size if size > 3583 => Platform::XoChip,
+                3583 => Platform::SuperChip,
+                _ => Platform::Chip8,
+            }
+        });
+        return Overrides {
+            platform,
+            wrap_sprites: option("clipQuirks").and_then(|v| v.as_bool()).map(|clip| !clip),
+            speed: option("tickrate").and_then(|v| v.as_integer()).filter(|&rate| rate > 0).map(|rate| rate as u32),
+        };
+    }
+
+    // Background, first plane, second plane and both planes, as in our palettes
+    pub fn colors(&self) -> Option<[[u8; 4]; 4]> {
+        let mut colors = [[0x00; 4]; 4];
+        let names = ["backgroundColor", "fillColor", "fillColor2", "blendColor"];
+        for (color, name) in colors.iter_mut().zip(names.iter()) {
+            *color = self.options.get(*name)?.as_str().and_then(crate::palette::Palette::parse_hex)?;
+        }
+        return Some(colors);
+    }
+}
+
+// The same values our config files use, with nulls dropped from objects
+fn from_json(value: serde_json::Value) -> Value {
+    match value {
+        serde_json::Value::Null => Value::Table(BTreeMap::new()),
+        serde_json::Value::Bool(b) => Value::Boolean(b),
+        serde_json::Value::Number(n) => match n.as_i64() {
+            Some(i) => Value::Integer(i),
+            None => Value::Float(n.as_f64().unwrap_or(0.0)),
+        },
+        serde_json::Value::String(s) => Value::String(s),
+        serde_json::Value::Array(a) => Value::Array(a.into_iter().map(from_json).collect()),
+        serde_json::Value::Object(o) => Value::Table(o.into_iter()
+            .filter(|(_, v)| !v.is_null())
+            .map(|(k, v)| (k, from_json(v)))
+            .collect()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WIDTH: u16 = 32;
+    const HEIGHT: u16 = 4;
+
+    // Hides `json` in a GIF the way Octo does, spread over as many frames as it takes
+    fn cartridge_gif(json: &str) -> Vec<u8> {
+        let mut payload = (json.len() as u32).to_be_bytes().to_vec();
+        payload.extend_from_slice(json.as_bytes());
+        let mut pixels: Vec<u8> = payload.iter().flat_map(|&b| vec![0x10 | b >> 4, 0x20 | b & 0x0F]).collect();
+        let frame_size = WIDTH as usize * HEIGHT as usize;
+        pixels.resize(pixels.len().div_ceil(frame_size) * frame_size, 0);
+        let palette: Vec<u8> = (0..=255).flat_map(|i| vec![i, i, i]).collect();
+        let mut bytes = Vec::new();
+        {
+            let mut encoder = gif::Encoder::new(&mut bytes, WIDTH, HEIGHT, &palette).unwrap();
+            for frame in pixels.chunks(frame_size) {
+                encoder.write_frame(&gif::Frame::from_indexed_pixels(WIDTH, HEIGHT, frame.to_vec(), None)).unwrap();
+            }
+        }
+        return bytes;
+    }
+
+    #[test]
+    fn reads_the_program_and_options_from_every_frame() {
+        let program = ": main\n  v0 := 1 # a \"quoted\" comment\n  loop again\n";
+        let json = serde_json::json!({
+            "program": program,
+            "options": {
+                "tickrate": 200,
+                "maxSize": 3583,
+                "clipQuirks": true,
+                "backgroundColor": "#112233",
+                "fillColor": "#FFCC00",
+                "fillColor2": "#FF6600",
+                "blendColor": "#662200",
+                "fontStyle": null,
+            },
+        }).to_string();
+        let bytes = cartridge_gif(&json);
+        let cartridge = parse(&bytes).unwrap();
+        assert_eq!(cartridge.program, program);
+        assert!(!cartridge.options.contains_key("fontStyle"));
+        let overrides = cartridge.overrides();
+        assert_eq!(overrides.platform, Some(Platform::SuperChip));
+        assert_eq!(overrides.wrap_sprites, Some(false));
+        assert_eq!(overrides.speed, Some(200));
+        assert_eq!(cartridge.colors(), Some([
+            [0x11, 0x22, 0x33, 0xFF],
+            [0xFF, 0xCC, 0x00, 0xFF],
+            [0xFF, 0x66, 0x00, 0xFF],
+            [0x66, 0x22, 0x00, 0xFF],
+        ]));
+    }
+
+    #[test]
+    fn rejects_images_without_a_cartridge() {
+        let mut bytes = cartridge_gif("{\"program\": \": main\"}");
+        assert!(parse(&bytes).is_ok());
+        assert!(parse(&cartridge_gif("[1, 2]")).is_err());
+        assert!(parse(&cartridge_gif("{\"options\": {}}")).is_err());
+        assert!(parse(&cartridge_gif("{\"program\": ")).is_err());
+        bytes.truncate(20);
+        assert!(parse(&bytes).is_err());
+        assert!(parse(b"not a gif").is_err());
+    }
+}
@@ -0,0 +1,83 @@
+mod cartridge;
+mod octo;
+
+use crate::platform::{Overrides, Platform};
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::path::Path;
+
+// A program read from disk with whatever its file says about how to run it
+pub struct Rom {
+    pub bytes: Vec<u8>,
+    // Of the file as it is on disk, which for cartridges and source isn't the program itself
+    pub hash: String,
+    // Settings implied by the extension or carried in a cartridge
+    pub overrides: Overrides,
+    // An Octo cartridge's background, fill, second fill and blend colors
+    pub colors: Option<[[u8; 4]; 4]>,
+}
+
+// Reads a raw binary, an Octo cartridge (.gif) or Octo source (.8o). The
+// .ch8/.c8, .sc8 and .xo8 extensions pick their platform
+pub fn load(path: &Path) -> io::Result<Rom> {
+    let file = read_bytes(path)?;
+    let hash = hash(&file);
+    let extension = path.extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let invalid = |e: String| io::Error::new(io::ErrorKind::InvalidData, e);
+    let mut rom = Rom {
+        bytes: Vec::new(),
+        hash,
+        overrides: Overrides::default(),
+        colors: None,
+    };
+    match extension.as_str() {
+        "gif" => {
+            let cartridge = cartridge::parse(&file).map_err(invalid)?;
+            rom.bytes = octo::assemble(&cartridge.program).map_err(invalid)?;
+            rom.overrides = cartridge.overrides();
+            rom.colors = cartridge.colors();
+        },
+        "8o" => {
+            let source = String::from_utf8(file).map_err(|_| invalid("source is not UTF-8".to_string()))?;
+            rom.bytes = octo::assemble(&source).map_err(invalid)?;
+        },
+        _ => {
+            rom.overrides.platform = match extension.as_str() {
+                "ch8" | "c8" => Some(Platform::Chip8),
+                "sc8" => Some(Platform::SuperChip),
+                "xo8" => Some(Platform::XoChip),
+                _ => None,
+            };
+            rom.bytes = file;
+        },
+    }
+    return Ok(rom);
+}
+
+pub fn read_bytes(path: &Path) -> io::Result<Vec<u8>> {
+    let mut f = File::open(path)?;
+    let mut read_buffer = Vec::new();
+    f.read_to_end(&mut read_buffer)?;
+    return Ok(read_buffer);
+}
+
+// The SHA-1 of a ROM file in lowercase hex, which identifies it regardless of its name
+pub fn hash(bytes: &[u8]) -> String {
+    return sha1_smol::Sha1::from(bytes).digest().to_string();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashes_the_fips_180_examples() {
+        assert_eq!(hash(b""), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+        assert_eq!(hash(b"abc"), "a9993e364706816aba3e25717850c26c9cd0d89d");
+        assert_eq!(hash(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"), "84983e441c3bd26ebaae4aa1f95129e5e54670f1");
+        assert_eq!(hash(&vec![b'a'; 1_000_000]), "34aa973cd4c4daa4f61eeb2bdbad27316534016f");
+    }
+}
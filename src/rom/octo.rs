@@ -0,0 +1,826 @@
+// An assembler for the Octo language, which is what Octo cartridges and .8o
+// files hold. It covers the instructions, control flow, labels, constants,
+// aliases, macros and :calc expressions, but not :stringmode or :assert
+use std::collections::HashMap;
+
+const START: usize = 0x200;
+
+struct Token {
+    text: String,
+    line: usize,
+}
+
+// Where an address goes once the label it names has been defined
+#[derive(Copy, Clone)]
+enum Fixup {
+    // The low 12 bits of the instruction at the address
+    Addr,
+    // The whole word following an F000
+    Long,
+    // The two v0/v1 loads of an :unpack with this high nibble
+    Unpack(u8),
+}
+
+enum Block {
+    // The jump over the block for when the condition is false
+    Begin(usize),
+    // The jump from the end of the true part over the else part
+    Else(usize),
+    // The loop's first instruction and the jumps out of it from `while`
+    Loop(usize, Vec<usize>),
+}
+
+// A test that makes the following instruction conditional: the instructions
+// that set it up and the skips taken when the condition is false or true
+struct Condition {
+    setup: Vec<u16>,
+    skip: u16,
+    inverted: u16,
+}
+
+impl Condition {
+    fn new(skip: u16, inverted: u16) -> Self {
+        return Condition { setup: Vec::new(), skip, inverted };
+    }
+}
+
+struct Macro {
+    params: Vec<String>,
+    body: Vec<Token>,
+}
+
+struct Assembler {
+    // Unread tokens, last first, so macro bodies can be pushed back on
+    tokens: Vec<Token>,
+    line: usize,
+    rom: Vec<u8>,
+    here: usize,
+    end: usize,
+    labels: HashMap<String, usize>,
+    constants: HashMap<String, i64>,
+    aliases: HashMap<String, u8>,
+    macros: HashMap<String, Macro>,
+    fixups: Vec<(String, usize, Fixup, usize)>,
+    blocks: Vec<Block>,
+}
+
+// Assembles `source` into a program image to be loaded at 0x200. Like Octo,
+// the program starts with a jump to the `main` label
+pub fn assemble(source: &str) -> Result<Vec<u8>, String> {
+    let mut tokens = Vec::new();
+    for (line_no, line) in source.lines().enumerate() {
+        let code = match line.find('#') {
+            Some(idx) => &line[..idx],
+            None => line,
+        };
+        for text in code.split_whitespace() {
+            tokens.push(Token { text: text.to_string(), line: line_no + 1 });
+        }
+    }
+    tokens.reverse();
+    let mut assembler = Assembler {
+        tokens,
+        line: 1,
+        rom: vec![0; 0x10000],
+        here: START,
+        end: START,
+        labels: HashMap::new(),
+        constants: HashMap::new(),
+        aliases: HashMap::new(),
+        macros: HashMap::new(),
+        fixups: Vec::new(),
+        blocks: Vec::new(),
+    };
+    assembler.fixups.push(("main".to_string(), START, Fixup::Addr, 1));
+    assembler.emit(0x1000)?;
+    while !assembler.tokens.is_empty() {
+        assembler.statement()?;
+    }
+    return assembler.finish();
+}
+
+impl Assembler {
+    fn error<T>(&self, message: String) -> Result<T, String> {
+        return Err(format!("line {}: {}", self.line, message));
+    }
+
+    fn next(&mut self) -> Result<String, String> {
+        match self.tokens.pop() {
+            Some(token) => {
+                self.line = token.line;
+                return Ok(token.text);
+            },
+            None => return self.error("unexpected end of program".to_string()),
+        }
+    }
+
+    fn peek(&self) -> Option<&str> {
+        return self.tokens.last().map(|token| token.text.as_str());
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), String> {
+        let token = self.next()?;
+        if token != expected {
+            return self.error(format!("expected {} but found {}", expected, token));
+        }
+        return Ok(());
+    }
+
+    fn byte(&mut self, byte: u8) -> Result<(), String> {
+        if self.here >= self.rom.len() {
+            return self.error("program is too large".to_string());
+        }
+        self.rom[self.here] = byte;
+        self.here += 1;
+        self.end = self.end.max(self.here);
+        return Ok(());
+    }
+
+    fn emit(&mut self, word: u16) -> Result<(), String> {
+        self.byte((word >> 8) as u8)?;
+        return self.byte(word as u8);
+    }
+
+    fn patch(&mut self, addr: usize, word: u16) {
+        self.rom[addr] = (word >> 8) as u8;
+        self.rom[addr + 1] = word as u8;
+    }
+
+    // A jump to `target`, which has to be within reach of 12 bits
+    fn jump(&self, target: usize) -> Result<u16, String> {
+        if target > 0xFFF {
+            return self.error(format!("jump target {:X} is out of range", target));
+        }
+        return Ok(0x1000 | target as u16);
+    }
+
+    fn word_at(&self, addr: usize) -> u16 {
+        return (self.rom[addr] as u16) << 8 | self.rom[addr + 1] as u16;
+    }
+
+    fn define_label(&mut self, name: String, addr: usize) -> Result<(), String> {
+        if self.labels.contains_key(&name) {
+            return self.error(format!("label {} is defined twice", name));
+        }
+        self.labels.insert(name, addr);
+        return Ok(());
+    }
+
+    fn register(&mut self) -> Result<u8, String> {
+        let token = self.next()?;
+        match self.parse_register(&token) {
+            Some(reg) => return Ok(reg),
+            None => return self.error(format!("expected a register but found {}", token)),
+        }
+    }
+
+    fn parse_register(&self, token: &str) -> Option<u8> {
+        if let Some(reg) = self.aliases.get(token) {
+            return Some(*reg);
+        }
+        let lower = token.to_lowercase();
+        let digit = lower.strip_prefix('v')?;
+        if digit.len() != 1 {
+            return None;
+        }
+        return u8::from_str_radix(digit, 16).ok();
+    }
+
+    // A number, constant or label already defined
+    fn lookup(&self, token: &str) -> Option<i64> {
+        if let Some(value) = parse_number(token) {
+            return Some(value);
+        }
+        if let Some(value) = self.constants.get(token) {
+            return Some(*value);
+        }
+        return self.labels.get(token).map(|addr| *addr as i64);
+    }
+
+    fn number(&mut self) -> Result<i64, String> {
+        let token = self.next()?;
+        if token == "{" {
+            return self.calc();
+        }
+        match self.lookup(&token) {
+            Some(value) => return Ok(value),
+            None => return self.error(format!("unknown value {}", token)),
+        }
+    }
+
+    fn immediate(&mut self) -> Result<u8, String> {
+        let value = self.number()?;
+        if !(-128..=255).contains(&value) {
+            return self.error(format!("{} does not fit in a byte", value));
+        }
+        return Ok(value as u8);
+    }
+
+    fn nibble(&mut self) -> Result<u16, String> {
+        let value = self.number()?;
+        if !(0..=15).contains(&value) {
+            return self.error(format!("{} does not fit in a nibble", value));
+        }
+        return Ok(value as u16);
+    }
+
+    // Emits `opcode` with an address, leaving a fixup if its label comes later
+    fn emit_addr(&mut self, opcode: u16) -> Result<(), String> {
+        let token = self.next()?;
+        let addr = if token == "{" { Some(self.calc()?) } else { self.lookup(&token) };
+        match addr {
+            Some(addr) if (0..=0xFFF).contains(&addr) => return self.emit(opcode | addr as u16),
+            Some(addr) => return self.error(format!("address {:X} is out of range", addr)),
+            None => {
+                self.fixups.push((token, self.here, Fixup::Addr, self.line));
+                return self.emit(opcode);
+            },
+        }
+    }
+
+    fn statement(&mut self) -> Result<(), String> {
+        let token = self.next()?;
+        if let Some(reg) = self.parse_register(&token) {
+            return self.assign(reg);
+        }
+        if let Some(value) = parse_number(&token) {
+            if !(-128..=255).contains(&value) {
+                return self.error(format!("{} does not fit in a byte", value));
+            }
+            return self.byte(value as u8);
+        }
+        match token.as_str() {
+            ":" => {
+                let name = self.next()?;
+                return self.define_label(name, self.here);
+            },
+            ":next" => {
+                let name = self.next()?;
+                return self.define_label(name, self.here + 1);
+            },
+            ":const" => {
+                let name = self.next()?;
+                let value = self.number()?;
+                self.constants.insert(name, value);
+            },
+            ":calc" => {
+                let name = self.next()?;
+                self.expect("{")?;
+                let value = self.calc()?;
+                self.constants.insert(name, value);
+            },
+            ":alias" => {
+                let name = self.next()?;
+                let reg = self.register()?;
+                self.aliases.insert(name, reg);
+            },
+            ":org" => {
+                let addr = self.number()?;
+                if !(0..0x10000).contains(&addr) {
+                    return self.error(format!("address {:X} is out of range", addr));
+                }
+                self.here = addr as usize;
+            },
+            ":byte" => {
+                let value = self.immediate()?;
+                self.byte(value)?;
+            },
+            ":call" => return self.emit_addr(0x2000),
+            ":unpack" => {
+                let nibble = self.nibble()? as u8;
+                let token = self.next()?;
+                let addr = match self.lookup(&token) {
+                    Some(addr) => addr as u16,
+                    None => {
+                        self.fixups.push((token, self.here, Fixup::Unpack(nibble), self.line));
+                        0
+                    },
+                };
+                self.emit(0x6000 | (nibble as u16) << 4 | (addr >> 8) & 0x0F)?;
+                self.emit(0x6100 | (addr & 0xFF))?;
+            },
+            ":macro" => return self.define_macro(),
+            // Debugging aids for Octo's own emulator
+            ":breakpoint" | ":proto" => {
+                self.next()?;
+            },
+            ":monitor" => {
+                self.next()?;
+                self.next()?;
+            },
+            "return" | ";" => return self.emit(0x00EE),
+            "clear" => return self.emit(0x00E0),
+            "hires" => return self.emit(0x00FF),
+            "lores" => return self.emit(0x00FE),
+            "exit" => return self.emit(0x00FD),
+            "scroll-down" => {
+                let rows = self.nibble()?;
+                self.emit(0x00C0 | rows)?;
+            },
+            "scroll-up" => {
+                let rows = self.nibble()?;
+                self.emit(0x00D0 | rows)?;
+            },
+            "scroll-right" => return self.emit(0x00FB),
+            "scroll-left" => return self.emit(0x00FC),
+            "audio" => return self.emit(0xF002),
+            "plane" => {
+                let mask = self.nibble()?;
+                self.emit(0xF001 | mask << 8)?;
+            },
+            "jump" => return self.emit_addr(0x1000),
+            "jump0" => return self.emit_addr(0xB000),
+            "native" => return self.emit_addr(0x0000),
+            "sprite" => {
+                let x = self.register()? as u16;
+                let y = self.register()? as u16;
+                let rows = self.nibble()?;
+                self.emit(0xD000 | x << 8 | y << 4 | rows)?;
+            },
+            "bcd" => return self.register_op(0xF033),
+            "saveflags" => return self.register_op(0xF075),
+            "loadflags" => return self.register_op(0xF085),
+            "save" | "load" => {
+                let x = self.register()? as u16;
+                if self.peek() == Some("-") {
+                    self.next()?;
+                    let y = self.register()? as u16;
+                    let op = if token == "save" { 0x5002 } else { 0x5003 };
+                    self.emit(op | x << 8 | y << 4)?;
+                } else {
+                    let op = if token == "save" { 0xF055 } else { 0xF065 };
+                    self.emit(op | x << 8)?;
+                }
+            },
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let op = match token.as_str() {
+                    "delay" => 0xF015,
+                    "buzzer" => 0xF018,
+                    _ => 0xF03A,
+                };
+                return self.register_op(op);
+            },
+            "i" => {
+                let op = self.next()?;
+                match op.as_str() {
+                    ":=" => match self.peek() {
+                        Some("long") => {
+                            self.next()?;
+                            self.emit(0xF000)?;
+                            let token = self.next()?;
+                            let addr = match self.lookup(&token) {
+                                Some(addr) => addr as u16,
+                                None => {
+                                    self.fixups.push((token, self.here, Fixup::Long, self.line));
+                                    0
+                                },
+                            };
+                            self.emit(addr)?;
+                        },
+                        Some("hex") => {
+                            self.next()?;
+                            return self.register_op(0xF029);
+                        },
+                        Some("bighex") => {
+                            self.next()?;
+                            return self.register_op(0xF030);
+                        },
+                        _ => return self.emit_addr(0xA000),
+                    },
+                    "+=" => return self.register_op(0xF01E),
+                    _ => return self.error(format!("unknown operator i {}", op)),
+                }
+            },
+            "if" => {
+                let condition = self.condition()?;
+                for word in condition.setup.iter() {
+                    self.emit(*word)?;
+                }
+                match self.next()?.as_str() {
+                    "then" => {
+                        self.emit(condition.skip)?;
+                        return self.statement();
+                    },
+                    "begin" => {
+                        self.emit(condition.inverted)?;
+                        self.blocks.push(Block::Begin(self.here));
+                        self.emit(0x1000)?;
+                    },
+                    other => return self.error(format!("expected then or begin but found {}", other)),
+                }
+            },
+            "else" => match self.blocks.pop() {
+                Some(Block::Begin(jump)) => {
+                    self.blocks.push(Block::Else(self.here));
+                    self.emit(0x1000)?;
+                    let word = self.jump(self.here)?;
+                    self.patch(jump, word);
+                },
+                _ => return self.error("else without begin".to_string()),
+            },
+            "end" => match self.blocks.pop() {
+                Some(Block::Begin(jump)) | Some(Block::Else(jump)) => {
+                    let word = self.jump(self.here)?;
+                    self.patch(jump, word);
+                },
+                _ => return self.error("end without begin".to_string()),
+            },
+            "loop" => self.blocks.push(Block::Loop(self.here, Vec::new())),
+            "while" => {
+                let condition = self.condition()?;
+                for word in condition.setup.iter() {
+                    self.emit(*word)?;
+                }
+                self.emit(condition.inverted)?;
+                let jump = self.here;
+                let exits = self.blocks.iter_mut().rev().find_map(|block| match block {
+                    Block::Loop(_, exits) => Some(exits),
+                    _ => None,
+                });
+                match exits {
+                    Some(exits) => exits.push(jump),
+                    None => return self.error("while outside a loop".to_string()),
+                }
+                self.emit(0x1000)?;
+            },
+            "again" => match self.blocks.pop() {
+                Some(Block::Loop(start, exits)) => {
+                    let word = self.jump(start)?;
+                    self.emit(word)?;
+                    for jump in exits {
+                        let word = self.jump(self.here)?;
+                        self.patch(jump, word);
+                    }
+                },
+                _ => return self.error("again without loop".to_string()),
+            },
+            _ if self.macros.contains_key(&token) => return self.expand_macro(&token),
+            _ if token.starts_with(':') => return self.error(format!("unsupported directive {}", token)),
+            // Anything else names a subroutine to call
+            _ => {
+                self.tokens.push(Token { text: token, line: self.line });
+                return self.emit_addr(0x2000);
+            },
+        }
+        return Ok(());
+    }
+
+    fn register_op(&mut self, opcode: u16) -> Result<(), String> {
+        let x = self.register()? as u16;
+        return self.emit(opcode | x << 8);
+    }
+
+    fn assign(&mut self, x: u8) -> Result<(), String> {
+        let x = x as u16;
+        let op = self.next()?;
+        let rhs = self.peek().and_then(|token| self.parse_register(token));
+        if let Some(y) = rhs {
+            self.next()?;
+            let y = y as u16;
+            let low = match op.as_str() {
+                ":=" => 0x0,
+                "|=" => 0x1,
+                "&=" => 0x2,
+                "^=" => 0x3,
+                "+=" => 0x4,
+                "-=" => 0x5,
+                ">>=" => 0x6,
+                "=-" => 0x7,
+                "<<=" => 0xE,
+                _ => return self.error(format!("unknown operator {}", op)),
+            };
+            return self.emit(0x8000 | x << 8 | y << 4 | low);
+        }
+        match op.as_str() {
+            ":=" => match self.peek() {
+                Some("key") => {
+                    self.next()?;
+                    return self.emit(0xF00A | x << 8);
+                },
+                Some("delay") => {
+                    self.next()?;
+                    return self.emit(0xF007 | x << 8);
+                },
+                Some("random") => {
+                    self.next()?;
+                    let mask = self.immediate()? as u16;
+                    return self.emit(0xC000 | x << 8 | mask);
+                },
+                _ => {
+                    let value = self.immediate()? as u16;
+                    return self.emit(0x6000 | x << 8 | value);
+                },
+            },
+            "+=" => {
+                let value = self.immediate()? as u16;
+                return self.emit(0x7000 | x << 8 | value);
+            },
+            "-=" => {
+                let value = self.immediate()?.wrapping_neg() as u16;
+                return self.emit(0x7000 | x << 8 | value);
+            },
+            _ => return self.error(format!("operator {} needs a register", op)),
+        }
+    }
+
+    // Orderings compare through VF, whose borrow flag from a subtraction of
+    // the two sides settles the test
+    fn condition(&mut self) -> Result<Condition, String> {
+        let x = self.register()? as u16;
+        let op = self.next()?;
+        match op.as_str() {
+            "key" => return Ok(Condition::new(0xE0A1 | x << 8, 0xE09E | x << 8)),
+            "-key" => return Ok(Condition::new(0xE09E | x << 8, 0xE0A1 | x << 8)),
+            _ => {},
+        }
+        let rhs = self.peek().and_then(|token| self.parse_register(token));
+        let (y, value) = match rhs {
+            Some(y) => {
+                self.next()?;
+                (Some(y as u16), 0)
+            },
+            None => (None, self.immediate()? as u16),
+        };
+        match (op.as_str(), y) {
+            ("==", Some(y)) => return Ok(Condition::new(0x9000 | x << 8 | y << 4, 0x5000 | x << 8 | y << 4)),
+            ("!=", Some(y)) => return Ok(Condition::new(0x5000 | x << 8 | y << 4, 0x9000 | x << 8 | y << 4)),
+            ("==", None) => return Ok(Condition::new(0x4000 | x << 8 | value, 0x3000 | x << 8 | value)),
+            ("!=", None) => return Ok(Condition::new(0x3000 | x << 8 | value, 0x4000 | x << 8 | value)),
+            _ => {},
+        }
+        let load = match y {
+            Some(y) => 0x8F00 | y << 4,
+            None => 0x6F00 | value,
+        };
+        // VF -= x leaves the flag set when rhs >= x, and VF =- x when x >= rhs
+        let (subtract, skip) = match op.as_str() {
+            ">" => (0x8F05, 0x3F01),
+            "<=" => (0x8F05, 0x3F00),
+            "<" => (0x8F07, 0x3F01),
+            ">=" => (0x8F07, 0x3F00),
+            _ => return self.error(format!("unknown comparison {}", op)),
+        };
+        return Ok(Condition {
+            setup: vec![load, subtract | x << 4],
+            skip,
+            inverted: skip ^ 0x0001,
+        });
+    }
+
+    fn define_macro(&mut self) -> Result<(), String> {
+        let name = self.next()?;
+        let mut params = Vec::new();
+        loop {
+            let token = self.next()?;
+            if token == "{" {
+                break;
+            }
+            params.push(token);
+        }
+        let mut body = Vec::new();
+        let mut depth = 1;
+        loop {
+            let token = match self.tokens.pop() {
+                Some(token) => token,
+                None => return self.error(format!("macro {} is never closed", name)),
+            };
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" => depth -= 1,
+                _ => {},
+            }
+            if depth == 0 {
+                break;
+            }
+            body.push(token);
+        }
+        self.macros.insert(name, Macro { params, body });
+        return Ok(());
+    }
+
+    fn expand_macro(&mut self, name: &str) -> Result<(), String> {
+        let count = self.macros[name].params.len();
+        let mut args = Vec::with_capacity(count);
+        for _ in 0..count {
+            args.push(self.next()?);
+        }
+        let line = self.line;
+        let expansion: Vec<Token> = self.macros[name].body.iter().rev().map(|token| {
+            let text = match self.macros[name].params.iter().position(|param| *param == token.text) {
+                Some(idx) => args[idx].clone(),
+                None => token.text.clone(),
+            };
+            Token { text, line }
+        }).collect();
+        self.tokens.extend(expansion);
+        return Ok(());
+    }
+
+    // Evaluates the rest of a { } expression. As in Octo, operators have no
+    // precedence and group from the right
+    fn calc(&mut self) -> Result<i64, String> {
+        let value = self.calc_expression()?;
+        self.expect("}")?;
+        return Ok(value.floor() as i64);
+    }
+
+    fn calc_expression(&mut self) -> Result<f64, String> {
+        let lhs = self.calc_term()?;
+        let op = match self.peek() {
+            Some(op) if op != "}" && op != ")" => op.to_string(),
+            _ => return Ok(lhs),
+        };
+        self.next()?;
+        let rhs = self.calc_expression()?;
+        let value = match op.as_str() {
+            "+" => lhs + rhs,
+            "-" => lhs - rhs,
+            "*" => lhs * rhs,
+            "/" => lhs / rhs,
+            "%" => lhs % rhs,
+            "&" => (lhs as i64 & rhs as i64) as f64,
+            "|" => (lhs as i64 | rhs as i64) as f64,
+            "^" => (lhs as i64 ^ rhs as i64) as f64,
+            "<<" => ((lhs as i64) << (rhs as i64)) as f64,
+            ">>" => ((lhs as i64) >> (rhs as i64)) as f64,
+            _ => return self.error(format!("unknown operator {}", op)),
+        };
+        return Ok(value);
+    }
+
+    fn calc_term(&mut self) -> Result<f64, String> {
+        let token = self.next()?;
+        match token.as_str() {
+            "(" => {
+                let value = self.calc_expression()?;
+                self.expect(")")?;
+                return Ok(value);
+            },
+            "-" => return Ok(-self.calc_term()?),
+            "~" => return Ok(!(self.calc_term()? as i64) as f64),
+            _ => match self.lookup(&token) {
+                Some(value) => return Ok(value as f64),
+                None => return self.error(format!("unknown value {}", token)),
+            },
+        }
+    }
+
+    fn finish(mut self) -> Result<Vec<u8>, String> {
+        if !self.blocks.is_empty() {
+            return self.error("a begin or loop is never closed".to_string());
+        }
+        for (name, addr, fixup, line) in std::mem::take(&mut self.fixups) {
+            self.line = line;
+            let target = match self.labels.get(&name) {
+                Some(target) => *target as u16,
+                None => return self.error(format!("undefined name {}", name)),
+            };
+            match fixup {
+                Fixup::Addr => {
+                    if target > 0xFFF {
+                        return self.error(format!("{} is out of range at {:X}", name, target));
+                    }
+                    let word = self.word_at(addr);
+                    self.patch(addr, word & 0xF000 | target);
+                },
+                Fixup::Long => self.patch(addr, target),
+                Fixup::Unpack(nibble) => {
+                    self.patch(addr, 0x6000 | (nibble as u16) << 4 | (target >> 8) & 0x0F);
+                    self.patch(addr + 2, 0x6100 | (target & 0xFF));
+                },
+            }
+        }
+        self.rom.truncate(self.end);
+        return Ok(self.rom.split_off(START));
+    }
+}
+
+fn parse_number(token: &str) -> Option<i64> {
+    let (negative, digits) = match token.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, token),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if let Some(binary) = digits.strip_prefix("0b") {
+        i64::from_str_radix(binary, 2).ok()?
+    } else if !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit()) {
+        digits.parse().ok()?
+    } else {
+        return None;
+    };
+    return Some(if negative { -value } else { value });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(source: &str) -> Vec<u16> {
+        let rom = assemble(source).unwrap();
+        return rom.chunks(2).map(|pair| (pair[0] as u16) << 8 | *pair.get(1).unwrap_or(&0) as u16).collect();
+    }
+
+    // Expected words were worked out by hand from the CHIP-8 encodings
+    #[test]
+    fn assembles_instructions_and_labels() {
+        let source = "
+            : main
+              v0 := 5
+              v1 += 2
+              i := tile
+              sprite v0 v1 5
+              loop again
+            : tile 0xF0 0x90
+        ";
+        assert_eq!(assemble(source).unwrap(), vec![
+            0x12, 0x02, 0x60, 0x05, 0x71, 0x02, 0xA2, 0x0C, 0xD0, 0x15, 0x12, 0x0A, 0xF0, 0x90,
+        ]);
+    }
+
+    #[test]
+    fn assembles_register_operations() {
+        assert_eq!(words("
+            : main
+              v3 := v4  v3 |= v4  v3 &= v4  v3 ^= v4  v3 += v4  v3 -= v4  v3 >>= v4  v3 =- v4  v3 <<= v4
+              v3 := random 0x1F  v3 := delay  v3 := key  delay := v3  buzzer := v3
+              i += v3  i := hex v3  i := bighex v3  bcd v3  save v3  load v3  saveflags v3  loadflags v3
+              clear  hires  lores  scroll-down 4  scroll-right  scroll-left  exit  return
+        "), vec![
+            0x1202,
+            0x8340, 0x8341, 0x8342, 0x8343, 0x8344, 0x8345, 0x8346, 0x8347, 0x834E,
+            0xC31F, 0xF307, 0xF30A, 0xF315, 0xF318,
+            0xF31E, 0xF329, 0xF330, 0xF333, 0xF355, 0xF365, 0xF375, 0xF385,
+            0x00E0, 0x00FF, 0x00FE, 0x00C4, 0x00FB, 0x00FC, 0x00FD, 0x00EE,
+        ]);
+    }
+
+    #[test]
+    fn assembles_conditionals_and_loops() {
+        assert_eq!(words("
+            : main
+              if v0 == 3 then v1 := 1
+              if v0 != v1 begin
+                v2 := 0
+              else
+                v2 := 1
+              end
+              loop
+                v0 += 1
+                while v0 != 8
+              again
+              if v1 key then v1 := 2
+        "), vec![
+            0x1202,
+            0x4003, 0x6101,
+            0x9010, 0x120E, 0x6200, 0x1210, 0x6201,
+            0x7001, 0x4008, 0x1218, 0x1210,
+            0xE1A1, 0x6102,
+        ]);
+    }
+
+    #[test]
+    fn assembles_directives() {
+        assert_eq!(words("
+            :const SPEED 4
+            # Operators group from the right, so this is 4 * 3
+            :calc TRIPLE { SPEED * 2 + 1 }
+            :alias px v3
+            :macro bump REG AMOUNT { REG += AMOUNT }
+            : main
+              px := TRIPLE
+              bump px SPEED
+              :unpack 0xA data
+              i := long data
+              :call sub
+            : sub
+              return
+            : data
+              :byte { TRIPLE << 1 }
+        "), vec![
+            0x1202,
+            0x630C, 0x7304, 0x60A2, 0x6112, 0xF000, 0x0212, 0x2210,
+            0x00EE,
+            0x1800,
+        ]);
+    }
+
+    #[test]
+    fn forward_references_are_resolved() {
+        assert_eq!(words(": main jump later v0 := 1 : later i := data : data 0xFF"), vec![
+            0x1202, 0x1206, 0x6001, 0xA208, 0xFF00,
+        ]);
+    }
+
+    #[test]
+    fn reports_mistakes_with_their_line() {
+        assert_eq!(assemble(": main\n  jump nowhere"), Err("line 2: undefined name nowhere".to_string()));
+        assert_eq!(assemble(": main\nloop\n  v0 += 1"), Err("line 3: a begin or loop is never closed".to_string()));
+        assert!(assemble(": main\n: main").is_err());
+        assert!(assemble(": main\nv0 := 256").is_err());
+        assert_eq!(assemble(": main\n:byte 300"), Err("line 2: 300 does not fit in a byte".to_string()));
+        assert_eq!(assemble(": main\n:byte { 128 * 2 }"), Err("line 2: 256 does not fit in a byte".to_string()));
+        assert_eq!(assemble(": main\n:byte 255 :byte -1"), Ok(vec![0x12, 0x02, 0xFF, 0xFF]));
+    }
+}
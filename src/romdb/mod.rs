@@ -0,0 +1,163 @@
+use crate::config::{Config, Value};
+use crate::platform::{Overrides, Platform, Settings};
+use std::io;
+
+// The database shipped with the emulator, built in so it is found wherever
+// the emulator is run from
+const SHIPPED: &str = include_str!("../../data/romdb.toml");
+// Local additions and corrections, which win over the shipped entries
+pub const DEFAULT_USER_PATH: &str = "romdb.toml";
+
+// What we know about one ROM. Entries are tables keyed by the SHA-1 of the
+// ROM file:
+//
+//     ["f13766c14aeb02ad8d4d103cb5eadd282d20cddc"]
+//     title = "Brix"
+//     author = "Andreas Gustafsson"
+//     platform = "chip8"
+//     speed = 6
+//     quirks.wrap_sprites = false
+//     keymap.4 = ["q", "left"]
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Entry {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub description: Option<String>,
+    pub overrides: Overrides,
+    // Suggested bindings, applied before any [roms.<name>.keymap] in the config
+    pub keymap: Option<Value>,
+}
+
+impl Entry {
+    fn parse(hash: &str, table: &Value) -> Result<Self, String> {
+        let string = |key: &str| -> Result<Option<String>, String> {
+            match table.get(key) {
+                Some(value) => value.as_str()
+                    .map(|s| Some(s.to_string()))
+                    .ok_or_else(|| format!("{}: {} must be a string", hash, key)),
+                None => Ok(None),
+            }
+        };
+        let mut entry = Entry {
+            title: string("title")?,
+            author: string("author")?,
+            description: string("description")?,
+            overrides: Overrides::default(),
+            keymap: table.get("keymap").cloned(),
+        };
+        if let Some(name) = string("platform")? {
+            let platform = Platform::from_name(&name)
+                .ok_or_else(|| format!("{}: unknown platform {}", hash, name))?;
+            entry.overrides.platform = Some(platform);
+        }
+        if let Some(speed) = table.get("speed") {
+            let speed = speed.as_integer().filter(|speed| *speed > 0)
+                .ok_or_else(|| format!("{}: speed must be a positive number", hash))?;
+            entry.overrides.speed = Some(speed as u32);
+        }
+        if let Some(wrap_sprites) = table.get("quirks.wrap_sprites") {
+            let wrap_sprites = wrap_sprites.as_bool()
+                .ok_or_else(|| format!("{}: quirks.wrap_sprites must be true or false", hash))?;
+            entry.overrides.wrap_sprites = Some(wrap_sprites);
+        }
+        return Ok(entry);
+    }
+
+    // Fills in anything `other` has, keeping our values for the rest
+    fn merge(&mut self, other: Entry) {
+        self.title = other.title.or_else(|| self.title.take());
+        self.author = other.author.or_else(|| self.author.take());
+        self.description = other.description.or_else(|| self.description.take());
+        self.overrides.platform = other.overrides.platform.or(self.overrides.platform);
+        self.overrides.wrap_sprites = other.overrides.wrap_sprites.or(self.overrides.wrap_sprites);
+        self.overrides.speed = other.overrides.speed.or(self.overrides.speed);
+        self.keymap = other.keymap.or_else(|| self.keymap.take());
+    }
+
+    // "Title by Author", or whichever of the two we have
+    pub fn display_name(&self) -> Option<String> {
+        match (&self.title, &self.author) {
+            (Some(title), Some(author)) => Some(format!("{} by {}", title, author)),
+            (Some(title), None) => Some(title.clone()),
+            (None, Some(author)) => Some(format!("by {}", author)),
+            (None, None) => None,
+        }
+    }
+}
+
+pub struct RomDatabase {
+    entries: Vec<(String, Entry)>,
+}
+
+impl RomDatabase {
+    // Takes the shipped database and then reads the user's from database.user
+    // or the default path, which may be missing
+    pub fn load(config: &Config) -> io::Result<Self> {
+        let invalid = |source: &str, e: String| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", source, e));
+        let mut database = RomDatabase { entries: Vec::new() };
+        let shipped = Config::parse(SHIPPED).map_err(|e| invalid("built-in ROM database", e))?;
+        database.add(&shipped).map_err(|e| invalid("built-in ROM database", e))?;
+
+        let user_path = config.get("database.user").and_then(|v| v.as_str()).unwrap_or(DEFAULT_USER_PATH);
+        let user = Config::load(user_path)?;
+        database.add(&user).map_err(|e| invalid(user_path, e))?;
+        return Ok(database);
+    }
+
+    fn add(&mut self, file: &Config) -> Result<(), String> {
+        let tables = match file.root().as_table() {
+            Some(tables) => tables,
+            None => return Ok(()),
+        };
+        for (hash, table) in tables.iter() {
+            let hash = hash.to_lowercase();
+            let entry = Entry::parse(&hash, table)?;
+            match self.entries.iter_mut().find(|(existing, _)| *existing == hash) {
+                Some((_, existing)) => existing.merge(entry),
+                None => self.entries.push((hash, entry)),
+            }
+        }
+        return Ok(());
+    }
+
+    pub fn lookup(&self, hash: &str) -> Option<&Entry> {
+        return self.entries.iter().find(|(existing, _)| existing == hash).map(|(_, entry)| entry);
+    }
+}
+
+// Settings for a ROM: the platform defaults, then what the ROM file itself
+// says, then the database entry, then anything given on the command line
+pub fn resolve(rom: &Overrides, entry: Option<&Entry>, overrides: &Overrides) -> Settings {
+    let mut settings = Settings::new(Platform::Chip8);
+    rom.apply(&mut settings);
+    if let Some(entry) = entry {
+        entry.overrides.apply(&mut settings);
+    }
+    overrides.apply(&mut settings);
+    return settings;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shipped_database_is_built_in() {
+        let config = Config::parse("[database]\nuser = \"/nonexistent/romdb.toml\"").unwrap();
+        let database = RomDatabase::load(&config).unwrap();
+        let brix = database.lookup("f13766c14aeb02ad8d4d103cb5eadd282d20cddc").unwrap();
+        assert_eq!(brix.title.as_deref(), Some("Brix"));
+    }
+
+    #[test]
+    fn bundled_roms_hash_to_their_entries() {
+        let database = RomDatabase::load(&Config::parse("[database]\nuser = \"/nonexistent/romdb.toml\"").unwrap()).unwrap();
+        let brix = crate::rom::load(std::path::Path::new("roms/BRIX")).unwrap();
+        assert_eq!(brix.hash, "f13766c14aeb02ad8d4d103cb5eadd282d20cddc");
+        for file in std::fs::read_dir("roms").unwrap() {
+            let path = file.unwrap().path();
+            let rom = crate::rom::load(&path).unwrap();
+            assert!(database.lookup(&rom.hash).is_some(), "{} ({}) has no entry", path.display(), rom.hash);
+        }
+    }
+}
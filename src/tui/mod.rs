@@ -0,0 +1,351 @@
+use crate::display::{Frame, FrameReader};
+use crate::game::{GameState, KeySource, KeyState};
+use crate::interpreter::Stats;
+use crate::keymap::Keymap;
+use crate::palette::Palette;
+use std::fmt::Write as FmtWrite;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::process::{Command, Stdio};
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::{thread, time};
+
+const FRAME_TIME: f64 = 1.0 / 60.0;
+// Terminals only report presses, so a key counts as held until auto-repeat
+// stops. The first press has to outlast the delay before repeating starts,
+// which is 250-600ms on common systems, and later ones the gap between repeats.
+const KEY_FIRST_HOLD: f64 = 0.6;
+const KEY_REPEAT_HOLD: f64 = 0.15;
+// How long after Esc a following byte still counts as part of an escape sequence
+const ESC_TIMEOUT: f64 = 0.05;
+// Escape sequences longer than this are garbage and thrown away
+const MAX_SEQUENCE: usize = 16;
+const ESC: u8 = 0x1B;
+const CTRL_C: u8 = 0x03;
+
+#[derive(Clone, PartialEq, Debug)]
+enum TermInput {
+    // A key named the way the keymap names it
+    Key(String),
+    Quit,
+}
+
+// Splits terminal input into keys, recognising the escape sequences for the
+// arrow keys and telling them apart from Esc on its own by timing
+struct InputParser {
+    sequence: Vec<u8>,
+    esc_at: Option<time::Instant>,
+}
+
+impl InputParser {
+    fn new() -> Self {
+        InputParser {
+            sequence: Vec::new(),
+            esc_at: None,
+        }
+    }
+
+    fn feed(&mut self, byte: u8, now: time::Instant) -> Option<TermInput> {
+        if self.sequence.is_empty() {
+            return match byte {
+                ESC => {
+                    self.sequence.push(byte);
+                    self.esc_at = Some(now);
+                    None
+                },
+                CTRL_C => Some(TermInput::Quit),
+                _ => Some(TermInput::Key((byte as char).to_string())),
+            };
+        }
+        if self.sequence.len() == 1 {
+            // Anything but CSI (Esc [) or SS3 (Esc O) means Esc was pressed alone
+            if byte != b'[' && byte != b'O' {
+                self.sequence.clear();
+                return Some(TermInput::Quit);
+            }
+            self.sequence.push(byte);
+            return None;
+        }
+        // SS3 has a single final byte, while CSI has parameters up to a final byte in @ to ~
+        let finished = self.sequence[1] == b'O' || (0x40..=0x7E).contains(&byte);
+        if !finished {
+            self.sequence.push(byte);
+            if self.sequence.len() > MAX_SEQUENCE {
+                self.sequence.clear();
+            }
+            return None;
+        }
+        self.sequence.clear();
+        let name = match byte {
+            b'A' => "up",
+            b'B' => "down",
+            b'C' => "right",
+            b'D' => "left",
+            _ => return None,
+        };
+        return Some(TermInput::Key(name.to_string()));
+    }
+
+    // Whether an Esc has gone unfollowed for long enough to be the Esc key itself
+    fn esc_timed_out(&mut self, now: time::Instant) -> bool {
+        let timed_out = match self.esc_at {
+            Some(at) => (now - at).as_secs_f64() >= ESC_TIMEOUT,
+            None => false,
+        };
+        if timed_out && self.sequence.len() == 1 {
+            return true;
+        }
+        if timed_out {
+            // A sequence that never finished
+            self.sequence.clear();
+            self.esc_at = None;
+        }
+        return false;
+    }
+}
+
+// Puts the terminal into raw mode and restores the previous settings when dropped
+struct RawMode {
+    saved: String,
+}
+
+impl RawMode {
+    fn enable() -> io::Result<Self> {
+        let saved = stty(&["-g"])?;
+        stty(&["raw", "-echo"])?;
+        return Ok(RawMode {
+            saved: saved.trim().to_string(),
+        });
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = stty(&[&self.saved]);
+        // Show the cursor, reset colors and leave the alternate screen
+        print!("\x1b[?25h\x1b[0m\x1b[?1049l");
+        let _ = io::stdout().flush();
+    }
+}
+
+fn stty(args: &[&str]) -> io::Result<String> {
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(File::open("/dev/tty")?)
+        .stderr(Stdio::inherit())
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::other("stty failed"));
+    }
+    return Ok(String::from_utf8_lossy(&output.stdout).to_string());
+}
+
+// Renders frames into the terminal with half-block characters, two pixels per
+// cell, and feeds terminal key presses to the keypad
+pub struct Tui {
+    state: Arc<Mutex<GameState>>,
+    frames: FrameReader,
+    stats: Arc<Stats>,
+    palette: Palette,
+    keymap: Keymap,
+    // Until when each keypad key counts as held
+    held_until: [Option<time::Instant>; 16],
+}
+
+impl Tui {
+    pub fn new(state: Arc<Mutex<GameState>>, frames: FrameReader, stats: Arc<Stats>, palette: Palette, keymap: Keymap) -> Self {
+        Tui {
+            state,
+            frames,
+            stats,
+            palette,
+            keymap,
+            held_until: [None; 16],
+        }
+    }
+
+    // Runs until Esc or Ctrl-C is pressed
+    pub fn start(&mut self) -> io::Result<()> {
+        let _raw_mode = RawMode::enable()?;
+        // Use the alternate screen and hide the cursor
+        print!("\x1b[?1049h\x1b[?25l\x1b[2J");
+        let input = spawn_input();
+        let mut parser = InputParser::new();
+
+        let frame_time = time::Duration::from_secs_f64(FRAME_TIME);
+        let mut last_second = time::Instant::now();
+        let mut frames_drawn = 0;
+        let mut last_instructions = self.stats.instructions.load(Ordering::Relaxed);
+        let (mut fps, mut ips) = (0, 0);
+        let mut out = String::new();
+        loop {
+            let started = time::Instant::now();
+            while let Ok(byte) = input.try_recv() {
+                match parser.feed(byte, started) {
+                    Some(TermInput::Quit) => return Ok(()),
+                    Some(TermInput::Key(name)) => self.press(&name, started),
+                    None => {},
+                }
+            }
+            if parser.esc_timed_out(started) {
+                return Ok(());
+            }
+            self.update_keys(started);
+
+            if last_second.elapsed().as_secs_f64() >= 1.0 {
+                let instructions = self.stats.instructions.load(Ordering::Relaxed);
+                ips = instructions - last_instructions;
+                last_instructions = instructions;
+                fps = frames_drawn;
+                frames_drawn = 0;
+                last_second = time::Instant::now();
+            }
+
+            out.clear();
+            out.push_str("\x1b[H");
+            draw_frame(self.frames.read(), &self.palette, &mut out);
+            let status = if self.stats.halted.load(Ordering::Relaxed) {
+                "  HALTED"
+            } else if self.stats.waiting_for_key.load(Ordering::Relaxed) {
+                "  WAITING FOR KEY"
+            } else {
+                ""
+            };
+            let _ = write!(out, "PC {:04X}  FPS {:3}  {:7} instr/s{}  (Esc to quit)\x1b[K",
+                self.stats.pc.load(Ordering::Relaxed), fps, ips, status);
+            print!("{}", out);
+            io::stdout().flush()?;
+            frames_drawn += 1;
+
+            let elapsed = started.elapsed();
+            if elapsed < frame_time {
+                thread::sleep(frame_time - elapsed);
+            }
+        }
+    }
+
+    fn press(&mut self, host_key: &str, now: time::Instant) {
+        for key_id in self.keymap.lookup(host_key) {
+            let held_until = &mut self.held_until[key_id as usize];
+            *held_until = Some(now + time::Duration::from_secs_f64(hold_time(*held_until, now)));
+        }
+    }
+
+    fn update_keys(&mut self, now: time::Instant) {
+        let mut state = self.state.lock().unwrap();
+        for (key_id, held_until) in self.held_until.iter().enumerate() {
+            let held = match held_until {
+                Some(until) => now < *until,
+                None => false,
+            };
+            let key_state = if held { KeyState::Pressed } else { KeyState::Released };
+            state.set_key_state(KeySource::Keyboard, key_id as u8, key_state);
+        }
+    }
+}
+
+// A press while the key is still held is an auto-repeat, and the next one
+// will follow shortly, while a fresh press has to last until repeating starts
+fn hold_time(held_until: Option<time::Instant>, now: time::Instant) -> f64 {
+    match held_until {
+        Some(until) if now < until => KEY_REPEAT_HOLD,
+        _ => KEY_FIRST_HOLD,
+    }
+}
+
+fn spawn_input() -> Receiver<u8> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let stdin = io::stdin();
+        for byte in stdin.lock().bytes() {
+            match byte {
+                Ok(byte) => if sender.send(byte).is_err() {
+                    break;
+                },
+                Err(_) => break,
+            }
+        }
+    });
+    return receiver;
+}
+
+// Each cell shows the top pixel as the foreground of '▀' and the bottom pixel as its background
+fn draw_frame(frame: &Frame, palette: &Palette, out: &mut String) {
+    let color = |x: usize, y: usize| -> [u8; 4] {
+        if y < frame.height {
+            palette.colors[frame.pixels[y * frame.width + x] as usize & 0x03]
+        } else {
+            palette.colors[0]
+        }
+    };
+    for y in (0..frame.height).step_by(2) {
+        let mut last = None;
+        for x in 0..frame.width {
+            let cell = (color(x, y), color(x, y + 1));
+            if last != Some(cell) {
+                let (top, bottom) = cell;
+                let _ = write!(out, "\x1b[38;2;{};{};{}m\x1b[48;2;{};{};{}m",
+                    top[0], top[1], top[2], bottom[0], bottom[1], bottom[2]);
+                last = Some(cell);
+            }
+            out.push('▀');
+        }
+        out.push_str("\x1b[0m\r\n");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(bytes: &[u8], now: time::Instant) -> Vec<TermInput> {
+        let mut parser = InputParser::new();
+        return bytes.iter().filter_map(|byte| parser.feed(*byte, now)).collect();
+    }
+
+    fn key(name: &str) -> TermInput {
+        return TermInput::Key(name.to_string());
+    }
+
+    #[test]
+    fn arrow_keys_are_keys_not_quit() {
+        let now = time::Instant::now();
+        assert_eq!(parse(b"\x1b[A\x1b[B\x1b[C\x1b[D", now), vec![key("up"), key("down"), key("right"), key("left")]);
+        // Application cursor mode and modifiers
+        assert_eq!(parse(b"\x1bOA\x1b[1;5D", now), vec![key("up"), key("left")]);
+        // Other sequences like Page Up are swallowed whole
+        assert_eq!(parse(b"\x1b[5~w", now), vec![key("w")]);
+        assert_eq!(parse(b"q\x03", now), vec![key("q"), TermInput::Quit]);
+    }
+
+    #[test]
+    fn esc_quits_only_once_nothing_follows() {
+        let start = time::Instant::now();
+        let later = start + time::Duration::from_secs_f64(ESC_TIMEOUT);
+        let mut parser = InputParser::new();
+        assert_eq!(parser.feed(ESC, start), None);
+        assert!(!parser.esc_timed_out(start));
+        assert!(parser.esc_timed_out(later));
+
+        let mut parser = InputParser::new();
+        parser.feed(ESC, start);
+        parser.feed(b'[', start);
+        assert_eq!(parser.feed(b'A', start), Some(key("up")));
+        assert!(!parser.esc_timed_out(later));
+
+        // Esc followed by anything but a sequence is Esc
+        assert_eq!(parse(b"\x1bq", start), vec![TermInput::Quit]);
+    }
+
+    #[test]
+    fn a_press_outlasts_the_repeat_delay() {
+        let start = time::Instant::now();
+        assert_eq!(hold_time(None, start), KEY_FIRST_HOLD);
+        let until = start + time::Duration::from_secs_f64(KEY_FIRST_HOLD);
+        assert_eq!(hold_time(Some(until), start + time::Duration::from_millis(500)), KEY_REPEAT_HOLD);
+        assert_eq!(hold_time(Some(until), until), KEY_FIRST_HOLD);
+    }
+}